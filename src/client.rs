@@ -1,48 +1,486 @@
 use std::env;
+use std::io::{self, BufReader};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
+use std::fmt;
+
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use chrono::{DateTime, FixedOffset, Utc};
+use futures::StreamExt;
 use once_cell::sync::OnceCell;
 use reqwest::{
     self,
-    header::{HeaderValue, CONTENT_TYPE},
+    header::{
+        HeaderMap, HeaderName, HeaderValue, AGE, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE,
+        COOKIE, DATE, ETAG, EXPIRES, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION,
+        RETRY_AFTER,
+    },
+    StatusCode,
 };
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
+use tokio::time::Instant;
+use tokio_util::io::{ReaderStream, StreamReader, SyncIoBridge};
+
+use url::Url;
 
 use crate::feed::Rss;
 
+mod discovery;
+mod limiter;
+mod local;
+
+pub use discovery::discover;
+pub use local::is_local;
+
 static RESP_SIZE_LIMIT: OnceCell<u64> = OnceCell::new();
 static CLIENT: OnceCell<reqwest::Client> = OnceCell::new();
+/// Doesn't follow redirects, `fetch_feed` follows them to find permanent ones
+static FEED_CLIENT: OnceCell<reqwest::Client> = OnceCell::new();
+static MAX_REDIRECTS: OnceCell<usize> = OnceCell::new();
+static READ_TIMEOUT: OnceCell<Duration> = OnceCell::new();
+static REQUEST_TIMEOUT: OnceCell<Duration> = OnceCell::new();
+static LIMITER: OnceCell<limiter::FetchLimiter> = OnceCell::new();
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// Malformed feeds larger than this aren't recovered, decompressed size
+const MAX_RECOVERY_LEN: u64 = 256 * 1024;
 
 #[derive(Error, Debug)]
 pub enum FeedError {
     #[error("network error")]
-    Network(#[from] reqwest::Error),
+    Network(#[source] reqwest::Error),
+    #[error("DNS lookup failed")]
+    Dns(#[source] reqwest::Error),
+    #[error("TLS error")]
+    Tls(#[source] reqwest::Error),
+    #[error("timed out connecting to the server")]
+    ConnectTimeout,
+    /// No response or body data for `--read-timeout`
+    #[error("timed out reading from the server")]
+    ReadTimeout,
+    /// The whole request took longer than `--request-timeout`
+    #[error("timed out fetching the feed")]
+    RequestTimeout,
+    /// 4xx or 5xx
+    #[error("{0}")]
+    Status(StatusCode),
+    /// 429 or 503 with `Retry-After`
+    #[error("{0}, retry after {}s", .1.as_secs())]
+    RetryAfter(StatusCode, Duration),
+    #[error("too many redirects")]
+    TooManyRedirects,
+    #[error("unsupported content type {0}")]
+    UnsupportedContentType(String),
+    /// URL of the page after redirects and the beginning of it,
+    /// `discover` finds feeds in it
+    #[error("got a web page instead of a feed")]
+    HtmlPage(String, Vec<u8>),
+    #[error("empty document")]
+    EmptyDocument,
     #[error("feed parsing failed")]
     Parsing(#[from] quick_xml::Error),
+    #[error("json feed parsing failed")]
+    JsonParsing(#[from] crate::feed::json::Error),
     #[error("feed is too large")]
     TooLarge(u64),
+    /// `file://` outside of `--local-feed-dir` or `exec:` not in `--exec-feed`
+    #[error("local feed source not allowed")]
+    SourceNotAllowed,
+    #[error("io error")]
+    Io(#[from] io::Error),
+    #[error("command failed, {0}")]
+    CommandFailed(std::process::ExitStatus),
+    #[error("command timed out")]
+    CommandTimeout,
+}
+
+/// How the fetcher deals with a failed feed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retry {
+    /// Likely temporary, keep fetching as usual
+    Normal,
+    /// Won't go away by itself soon, fetch at `max_interval` until it recovers
+    Backoff,
+}
+
+impl From<reqwest::Error> for FeedError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_redirect() {
+            return Self::TooManyRedirects;
+        }
+        if let Some(status) = err.status() {
+            return Self::Status(status);
+        }
+        if err.is_connect() {
+            if err.is_timeout() {
+                return Self::ConnectTimeout;
+            }
+            // hyper and the TLS backends don't expose error kinds,
+            // only messages are left
+            let mut messages = String::new();
+            let mut source = std::error::Error::source(&err);
+            while let Some(e) = source {
+                messages.push_str(&e.to_string().to_lowercase());
+                messages.push('\n');
+                source = e.source();
+            }
+            if messages.contains("dns error") {
+                return Self::Dns(err);
+            }
+            if ["certificate", "tls", "ssl", "handshake"]
+                .iter()
+                .any(|word| messages.contains(word))
+            {
+                return Self::Tls(err);
+            }
+        }
+        Self::Network(err)
+    }
 }
 
 impl FeedError {
     pub fn to_user_friendly(&self) -> String {
         match self {
             Self::Network(source) => tr!("network_error", source = source),
+            Self::Dns(_) => tr!("dns_error").into(),
+            Self::Tls(source) => tr!("tls_error", source = source),
+            Self::ConnectTimeout => tr!("connect_timeout").into(),
+            Self::ReadTimeout => tr!("read_timeout").into(),
+            Self::RequestTimeout => tr!("request_timeout").into(),
+            Self::Status(status) if status.is_client_error() => {
+                tr!("http_client_error", status = status)
+            }
+            Self::Status(status) => tr!("http_server_error", status = status),
+            Self::RetryAfter(status, delay) => {
+                tr!("http_retry_after", status = status, delay = delay.as_secs())
+            }
+            Self::TooManyRedirects => tr!("too_many_redirects").into(),
+            Self::UnsupportedContentType(content_type) => {
+                tr!("unsupported_content_type", content_type = content_type)
+            }
+            Self::HtmlPage(..) => tr!("not_a_feed").into(),
+            Self::EmptyDocument => tr!("empty_document").into(),
             Self::Parsing(source) => tr!("parsing_error", source = source),
+            Self::JsonParsing(source) => tr!("parsing_error", source = source),
             Self::TooLarge(limit) => {
                 tr!("rss_size_limit_exceeded", size = format_byte_size(*limit))
             }
+            Self::SourceNotAllowed => tr!("local_source_not_allowed").into(),
+            Self::Io(source) => tr!("local_source_error", source = source),
+            Self::CommandFailed(status) => tr!("command_failed", status = status),
+            Self::CommandTimeout => tr!("command_timeout").into(),
+        }
+    }
+
+    pub fn retry(&self) -> Retry {
+        match self {
+            Self::Dns(_)
+            | Self::Tls(_)
+            | Self::TooManyRedirects
+            | Self::UnsupportedContentType(_)
+            | Self::HtmlPage(..)
+            | Self::TooLarge(_)
+            | Self::SourceNotAllowed => Retry::Backoff,
+            Self::Status(status) => match *status {
+                StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS => Retry::Normal,
+                status if status.is_client_error() => Retry::Backoff,
+                _ => Retry::Normal,
+            },
+            Self::Network(_)
+            | Self::ConnectTimeout
+            | Self::ReadTimeout
+            | Self::RequestTimeout
+            | Self::RetryAfter(..)
+            | Self::EmptyDocument
+            | Self::Parsing(_)
+            | Self::JsonParsing(_)
+            | Self::Io(_)
+            | Self::CommandFailed(_)
+            | Self::CommandTimeout => Retry::Normal,
         }
     }
 }
 
-pub async fn pull_feed(url: &str) -> Result<Rss, FeedError> {
-    let mut resp = CLIENT
-        .get()
-        .expect("CLIENT not initialized")
-        .get(url)
-        .send()
-        .await?
-        .error_for_status()?;
+/// `ETag` and `Last-Modified` of a previous response, for conditional requests
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Extra request settings of a subscription, they are secrets,
+/// never show them to users
+#[derive(Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RequestOptions {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cookies: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth: Option<Auth>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Auth {
+    Basic {
+        username: String,
+        password: Option<String>,
+    },
+    Bearer(String),
+}
+
+const OPTION_PREFIXES: [&str; 4] = ["header:", "cookie:", "basic:", "bearer:"];
+
+impl RequestOptions {
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty() && self.cookies.is_empty() && self.auth.is_none()
+    }
+
+    /// Whether a command argument is a request option
+    pub fn is_option(arg: &str) -> bool {
+        OPTION_PREFIXES.iter().any(|prefix| arg.starts_with(prefix))
+    }
+
+    /// Parse command arguments like `header:Name=value`, `cookie:name=value`,
+    /// `basic:user:password` and `bearer:token`. Names end at the first `=`,
+    /// or `:` for usernames, values are percent-decoded, so they can contain
+    /// spaces like `%20` and `%` itself as `%25`, `+` is kept as is.
+    /// Return `None` if any of them is invalid
+    pub fn parse(args: &[&str]) -> Option<Self> {
+        let mut options = RequestOptions::default();
+        let decode = |s: &str| {
+            percent_encoding::percent_decode_str(s)
+                .decode_utf8()
+                .ok()
+                .map(|s| s.into_owned())
+        };
+        let pair = |s: &str| {
+            let (name, value) = s.split_once('=')?;
+            if name.is_empty() {
+                return None;
+            }
+            Some((name.to_owned(), decode(value)?))
+        };
+        for arg in args {
+            if let Some(header) = arg.strip_prefix("header:") {
+                let (name, value) = pair(header)?;
+                HeaderName::from_bytes(name.as_bytes()).ok()?;
+                HeaderValue::from_str(&value).ok()?;
+                options.headers.push((name, value));
+            } else if let Some(cookie) = arg.strip_prefix("cookie:") {
+                let (name, value) = pair(cookie)?;
+                HeaderValue::from_str(&format!("{}={}", name, value)).ok()?;
+                options.cookies.push((name, value));
+            } else if let Some(credentials) = arg.strip_prefix("basic:") {
+                let (username, password) = match credentials.split_once(':') {
+                    Some((username, password)) => (username, Some(decode(password)?)),
+                    None => (credentials, None),
+                };
+                options.auth = Some(Auth::Basic {
+                    username: decode(username)?,
+                    password,
+                });
+            } else {
+                let token = decode(arg.strip_prefix("bearer:")?)?;
+                HeaderValue::from_str(&token).ok()?;
+                options.auth = Some(Auth::Bearer(token));
+            }
+        }
+        Some(options)
+    }
+
+    fn apply(&self, mut req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        for (name, value) in &self.headers {
+            req = req.header(name, value);
+        }
+        if !self.cookies.is_empty() {
+            let cookies: Vec<_> = self
+                .cookies
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value))
+                .collect();
+            req = req.header(COOKIE, cookies.join("; "));
+        }
+        match &self.auth {
+            Some(Auth::Basic { username, password }) => req.basic_auth(username, password.as_ref()),
+            Some(Auth::Bearer(token)) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+}
+
+/// Only names, in case it's logged
+impl fmt::Debug for RequestOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names = |pairs: &[(String, String)]| {
+            pairs
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>()
+        };
+        f.debug_struct("RequestOptions")
+            .field("headers", &names(&self.headers))
+            .field("cookies", &names(&self.cookies))
+            .field("auth", &self.auth)
+            .finish()
+    }
+}
+
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Auth::Basic { .. } => "Basic",
+            Auth::Bearer(_) => "Bearer",
+        })
+    }
+}
+
+/// Whether `request` options of `base` can be sent to `url` too
+pub fn same_origin(base: &str, url: &str) -> bool {
+    match (Url::parse(base), Url::parse(url)) {
+        (Ok(base), Ok(url)) => base.origin() == url.origin(),
+        _ => false,
+    }
+}
+
+/// How long a response is fresh
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freshness {
+    /// `Cache-Control: max-age`
+    MaxAge(Duration),
+    /// `Expires`
+    Expires(Duration),
+}
+
+pub enum Pulled {
+    Modified(Box<Rss>, Validators, Option<Freshness>),
+    NotModified(Option<Freshness>),
+    /// Only redirected by 301 and 308 to the URL
+    Moved(String, Box<Pulled>),
+}
+
+pub async fn pull_feed(url: &str, request: &RequestOptions) -> Result<Rss, FeedError> {
+    let mut pulled = pull_feed_if_modified(url, request, &Validators::default()).await?;
+    loop {
+        match pulled {
+            Pulled::Modified(feed, ..) => return Ok(*feed),
+            Pulled::Moved(_, moved) => pulled = *moved,
+            Pulled::NotModified(_) => unreachable!("not a conditional request"),
+        }
+    }
+}
+
+/// Conditional GET, the feed isn't downloaded again if it's not modified
+pub async fn pull_feed_if_modified(
+    url: &str,
+    request: &RequestOptions,
+    validators: &Validators,
+) -> Result<Pulled, FeedError> {
+    if local::is_local(url) {
+        let feed = local::pull(url, max_feed_size()).await?;
+        return Ok(Pulled::Modified(
+            Box::new(feed),
+            Validators::default(),
+            None,
+        ));
+    }
+    fetch_feed(url, request, validators).await
+}
+
+async fn fetch_feed(
+    url: &str,
+    request: &RequestOptions,
+    validators: &Validators,
+) -> Result<Pulled, FeedError> {
+    let client = FEED_CLIENT.get().expect("FEED_CLIENT not initialized");
+    let max_redirects = *MAX_REDIRECTS.get().expect("MAX_REDIRECTS not initialized");
+    let mut visited = Vec::new();
+    let mut permanent = true;
+    let limiter = LIMITER.get().expect("LIMITER not initialized");
+    // Starts with the first request, waiting for the limiter doesn't count
+    let mut deadline = None;
+    // Held until the body is read, every redirect is a request of its own
+    let (resp, _permit, deadline) = loop {
+        let target = visited.last().map(Url::as_str).unwrap_or(url);
+        let permit = limiter.acquire(target).await;
+        let deadline = *deadline.get_or_insert_with(|| Instant::now() + request_timeout());
+        let mut req = client.get(target);
+        // Like reqwest does, credentials don't follow redirects to other sites
+        if same_origin(url, target) {
+            req = request.apply(req);
+        }
+        if let Some(etag) = &validators.etag {
+            req = req.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            req = req.header(IF_MODIFIED_SINCE, last_modified);
+        }
+        let resp = with_deadline(deadline, req.send()).await?;
+        let status = resp.status();
+        let location = resp
+            .headers()
+            .get(LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|location| resp.url().join(location).ok());
+        match location {
+            Some(next) if status.is_redirection() && status != StatusCode::NOT_MODIFIED => {
+                if visited.len() >= max_redirects || visited.contains(&next) || next.as_str() == url
+                {
+                    return Err(FeedError::TooManyRedirects);
+                }
+                permanent &= status == StatusCode::MOVED_PERMANENTLY
+                    || status == StatusCode::PERMANENT_REDIRECT;
+                visited.push(next);
+            }
+            _ => break (resp, permit, deadline),
+        }
+    };
+    let moved = |pulled| match visited.last() {
+        Some(moved_to) if permanent => Pulled::Moved(moved_to.to_string(), Box::new(pulled)),
+        _ => pulled,
+    };
+    let status = resp.status();
+    if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+        if let Some(delay) = resp.headers().get(RETRY_AFTER).and_then(retry_after) {
+            return Err(FeedError::RetryAfter(status, delay));
+        }
+    }
+    if status.is_client_error() || status.is_server_error() {
+        return Err(FeedError::Status(status));
+    }
+    if let Some(content_type) = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .filter(|v| content_type_is_unsupported(v))
+    {
+        return Err(FeedError::UnsupportedContentType(
+            String::from_utf8_lossy(content_type.as_bytes()).into_owned(),
+        ));
+    }
+    let freshness = freshness(resp.headers());
+    // Only trust 304 if it was asked for
+    if status == StatusCode::NOT_MODIFIED && !validators.is_empty() {
+        return Ok(moved(Pulled::NotModified(freshness)));
+    }
+    let header = |name| {
+        resp.headers()
+            .get(name)
+            .and_then(|v: &HeaderValue| v.to_str().ok())
+            .map(str::to_owned)
+    };
+    let new_validators = Validators {
+        etag: header(ETAG),
+        last_modified: header(LAST_MODIFIED),
+    };
     let size_limit = *RESP_SIZE_LIMIT
         .get()
         .expect("RESP_SIZE_LIMIT not initialized");
@@ -53,61 +491,366 @@ pub async fn pull_feed(url: &str) -> Result<Rss, FeedError> {
         }
     }
 
-    let feed = if url.ends_with(".json")
+    let is_html = matches!(resp.headers().get(CONTENT_TYPE), Some(v) if discovery::is_html(v));
+    let is_json = url.ends_with(".json")
         || matches!(
             resp.headers().get(CONTENT_TYPE),
             Some(v) if content_type_is_json(v)
-        ) {
-        resp.json().await?
-    } else {
-        let mut buf = Vec::new(); // TODO: capacity?
-        while let Some(bytes) = resp.chunk().await? {
-            if !unlimited && buf.len() + bytes.len() > size_limit as usize {
-                return Err(FeedError::TooLarge(size_limit));
+        );
+    let charset = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(content_type_charset);
+    // reqwest only decodes gzip, br and deflate
+    let is_zstd = resp
+        .headers()
+        .get(CONTENT_ENCODING)
+        .is_some_and(|v| v.as_bytes().eq_ignore_ascii_case(b"zstd"));
+    // Relative URLs are resolved against the URL after redirects
+    let final_url = resp.url().clone();
+
+    // The parser only sees an `io::Error` when reading the body failed,
+    // the real error is kept here
+    let body_error = Arc::new(Mutex::new(None));
+    let fail = {
+        let body_error = body_error.clone();
+        move |err: FeedError| {
+            let e = io::Error::other(err.to_string());
+            *body_error.lock().unwrap() = Some(err);
+            e
+        }
+    };
+    let raw = futures::stream::try_unfold(resp, {
+        let fail = fail.clone();
+        move |mut resp| {
+            let fail = fail.clone();
+            async move {
+                // Servers trickling the body can't hold the limiter forever
+                match with_deadline(deadline, resp.chunk()).await {
+                    Ok(Some(bytes)) => Ok(Some((bytes, resp))),
+                    Ok(None) => Ok(None),
+                    Err(err) => Err(fail(err)),
+                }
+            }
+        }
+    });
+    let mut body: Pin<Box<dyn AsyncBufRead + Send>> = Box::pin(StreamReader::new(Box::pin(raw)));
+    if is_zstd {
+        body = Box::pin(tokio::io::BufReader::new(ZstdDecoder::new(body)));
+    }
+    // Files like `feed.xml.gz`, whatever their `Content-Type` is
+    if body
+        .fill_buf()
+        .await
+        .is_ok_and(|head| head.starts_with(&GZIP_MAGIC))
+    {
+        let mut decoder = GzipDecoder::new(body);
+        decoder.multiple_members(true);
+        body = Box::pin(tokio::io::BufReader::new(decoder));
+    }
+
+    if body.fill_buf().await.is_ok_and(|head| head.is_empty()) {
+        return Err(FeedError::EmptyDocument);
+    }
+
+    // Some servers send feeds as `text/html`, look at the content before discovery
+    if is_html && !body.fill_buf().await.is_ok_and(discovery::looks_like_feed) {
+        let limit = match size_limit {
+            0 => discovery::MAX_HTML_LEN,
+            size => size.min(discovery::MAX_HTML_LEN),
+        };
+        let mut html = Vec::new();
+        // Whatever is read is still worth looking at
+        let _ = body.take(limit).read_to_end(&mut html).await;
+        if let Some(err) = body_error.lock().unwrap().take() {
+            return Err(err);
+        }
+        return Err(FeedError::HtmlPage(final_url.into(), html));
+    }
+
+    // The body is streamed into the parser, it's gone once parsing failed.
+    // Keep a copy of small bodies for the recovering parser, larger ones
+    // aren't worth the memory of every fetch
+    let copy = Arc::new(Mutex::new((!is_json).then(Vec::new)));
+    // Decompressed size
+    let mut read = 0u64;
+    let body = ReaderStream::new(body).map({
+        let copy = copy.clone();
+        move |chunk| {
+            let bytes = chunk?;
+            read += bytes.len() as u64;
+            if !unlimited && read > size_limit {
+                return Err(fail(FeedError::TooLarge(size_limit)));
+            }
+            let mut copy = copy.lock().unwrap();
+            if read > MAX_RECOVERY_LEN {
+                *copy = None;
+            } else if let Some(copy) = &mut *copy {
+                copy.extend_from_slice(&bytes);
+            }
+            Ok(bytes)
+        }
+    });
+    let mut body = BufReader::new(SyncIoBridge::new(StreamReader::new(body)));
+
+    let url = url.to_owned();
+    let feed = tokio::task::spawn_blocking(move || -> Result<Rss, FeedError> {
+        if is_json {
+            return Ok(crate::feed::json::parse(body)?);
+        }
+        match crate::feed::parse(&mut body, charset.as_deref()) {
+            Err(err) => {
+                // The rest of the document
+                if io::copy(&mut body, &mut io::sink()).is_err() {
+                    return Err(err.into());
+                }
+                let Some(copy) = copy.lock().unwrap().take() else {
+                    return Err(err.into());
+                };
+                match crate::feed::parse_recovering(&copy[..], charset.as_deref()) {
+                    Ok(feed) => {
+                        eprintln!("Warning: malformed feed {}: {}, recovered", url, err);
+                        Ok(feed)
+                    }
+                    Err(_) => Err(err.into()),
+                }
             }
-            buf.extend_from_slice(&bytes);
+            result => Ok(result?),
         }
+    })
+    .await
+    .expect("feed parser panicked");
+    if let Some(err) = body_error.lock().unwrap().take() {
+        return Err(err);
+    }
+
+    Ok(moved(Pulled::Modified(
+        Box::new(crate::feed::fix_relative_url(feed?, final_url.as_str())),
+        new_validators,
+        freshness,
+    )))
+}
 
-        crate::feed::parse(std::io::Cursor::new(buf))?
+/// `Cache-Control: max-age` takes precedence over `Expires`
+fn freshness(headers: &HeaderMap) -> Option<Freshness> {
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|v: &HeaderValue| v.to_str().ok())
     };
+    let max_age = header(CACHE_CONTROL).and_then(|value| {
+        value.split(',').find_map(|directive| {
+            let (name, value) = directive.split_once('=')?;
+            if name.trim().eq_ignore_ascii_case("max-age") {
+                value.trim().trim_matches('"').parse::<u64>().ok()
+            } else {
+                None
+            }
+        })
+    });
+    if let Some(max_age) = max_age {
+        let age = header(AGE).and_then(|age| age.trim().parse::<u64>().ok());
+        let max_age = max_age.saturating_sub(age.unwrap_or_default());
+        return Some(Freshness::MaxAge(Duration::from_secs(max_age)));
+    }
+
+    let expires = header(EXPIRES)?;
+    // Relative to the server clock
+    let now = header(DATE)
+        .and_then(parse_http_date)
+        .unwrap_or_else(|| Utc::now().into());
+    // Invalid dates like "0" mean already expired
+    let lifetime = parse_http_date(expires)
+        .and_then(|expires| (expires - now).to_std().ok())
+        .unwrap_or_default();
+    Some(Freshness::Expires(lifetime))
+}
+
+/// Seconds or an HTTP date
+fn retry_after(value: &HeaderValue) -> Option<Duration> {
+    let value = value.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = parse_http_date(value)?;
+    Some(
+        date.signed_duration_since(Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
 
+fn parse_http_date(s: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc2822(s.trim()).ok()
+}
+
+/// Parse a feed body already in memory, e.g. pushed by a WebSub hub
+pub fn parse_feed_body(
+    url: &str,
+    content_type: Option<&HeaderValue>,
+    body: &[u8],
+) -> Result<Rss, FeedError> {
+    let is_json =
+        url.ends_with(".json") || matches!(content_type, Some(v) if content_type_is_json(v));
+    let charset = content_type.and_then(content_type_charset);
+    let feed = if is_json {
+        crate::feed::json::parse(body)?
+    } else {
+        match crate::feed::parse(body, charset.as_deref()) {
+            Err(err) => crate::feed::parse_recovering(body, charset.as_deref()).map_err(|_| err)?,
+            result => result?,
+        }
+    };
     Ok(crate::feed::fix_relative_url(feed, url))
 }
 
-pub fn init_client(bot_name: &str, insecue: bool, max_feed_size: u64) {
+pub fn http_client() -> &'static reqwest::Client {
+    CLIENT.get().expect("CLIENT not initialized")
+}
+
+/// Wait for the response or a chunk of the body, at most `--read-timeout`
+pub async fn with_read_timeout<T>(
+    fut: impl std::future::Future<Output = reqwest::Result<T>>,
+) -> Result<T, FeedError> {
+    match tokio::time::timeout(read_timeout(), fut).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(FeedError::ReadTimeout),
+    }
+}
+
+/// `with_read_timeout`, and no later than `deadline`
+async fn with_deadline<T>(
+    deadline: Instant,
+    fut: impl std::future::Future<Output = reqwest::Result<T>>,
+) -> Result<T, FeedError> {
+    match tokio::time::timeout_at(deadline, with_read_timeout(fut)).await {
+        Ok(result) => result,
+        Err(_) => Err(FeedError::RequestTimeout),
+    }
+}
+
+fn request_timeout() -> Duration {
+    *REQUEST_TIMEOUT
+        .get()
+        .expect("REQUEST_TIMEOUT not initialized")
+}
+
+pub fn read_timeout() -> Duration {
+    *READ_TIMEOUT.get().expect("READ_TIMEOUT not initialized")
+}
+
+pub fn init_local_sources(opt: &crate::Opt) {
+    local::init(
+        &opt.local_feed_dir,
+        &opt.exec_feed,
+        Duration::from_secs(opt.exec_timeout),
+    );
+}
+
+/// 0 is unlimited
+pub fn max_feed_size() -> u64 {
+    *RESP_SIZE_LIMIT
+        .get()
+        .expect("RESP_SIZE_LIMIT not initialized")
+}
+
+pub fn init_client(bot_name: &str, opt: &crate::Opt) -> reqwest::Result<()> {
     let mut headers = reqwest::header::HeaderMap::new();
-    let ua = format!(
-        concat!(
-            env!("CARGO_PKG_NAME"),
-            "/",
-            env!("CARGO_PKG_VERSION"),
-            " (+https://t.me/{})"
+    let ua = match &opt.user_agent {
+        Some(ua) => ua.clone(),
+        None => format!(
+            concat!(
+                env!("CARGO_PKG_NAME"),
+                "/",
+                env!("CARGO_PKG_VERSION"),
+                " (+https://t.me/{})"
+            ),
+            bot_name
         ),
-        bot_name
-    );
+    };
     headers.insert(
         reqwest::header::USER_AGENT,
         reqwest::header::HeaderValue::from_str(&ua).unwrap(),
     );
-    let mut client_builder = reqwest::Client::builder()
-        .timeout(Duration::from_secs(10))
-        .default_headers(headers)
-        .danger_accept_invalid_certs(insecue)
-        .redirect(reqwest::redirect::Policy::limited(5));
-
-    if env::var("RSSBOT_DONT_PROXY_FEEDS")
-        .or_else(|_| env::var("rssbot_dont_proxy_feeds"))
-        .is_ok()
-    {
-        client_builder = client_builder.no_proxy();
-    }
+    // zstd isn't supported by reqwest, `fetch_feed` decodes it
+    headers.insert(
+        reqwest::header::ACCEPT_ENCODING,
+        reqwest::header::HeaderValue::from_static("gzip, br, deflate, zstd"),
+    );
+    let build = |redirect| -> reqwest::Result<reqwest::Client> {
+        let mut client_builder = reqwest::Client::builder()
+            .connect_timeout(Duration::from_secs(opt.connect_timeout))
+            .default_headers(headers.clone())
+            .danger_accept_invalid_certs(opt.insecure)
+            .redirect(redirect);
 
-    let client = client_builder.build().unwrap();
+        if env::var("RSSBOT_DONT_PROXY_FEEDS")
+            .or_else(|_| env::var("rssbot_dont_proxy_feeds"))
+            .is_ok()
+        {
+            client_builder = client_builder.no_proxy();
+        }
+        // Explicit proxies replace the ones from environment variables,
+        // the first matching one is used
+        let no_proxy = reqwest::NoProxy::from_string(&opt.no_proxy.join(","));
+        let proxies = [
+            (&opt.http_proxy, reqwest::Proxy::http as fn(Url) -> _),
+            (&opt.https_proxy, reqwest::Proxy::https),
+            (&opt.proxy, reqwest::Proxy::all),
+        ];
+        for (url, proxy) in proxies {
+            if let Some(url) = url {
+                client_builder =
+                    client_builder.proxy(proxy(url.clone())?.no_proxy(no_proxy.clone()));
+            }
+        }
+        client_builder.build()
+    };
+    let client = build(reqwest::redirect::Policy::limited(opt.max_redirects))?;
+    let feed_client = build(reqwest::redirect::Policy::none())?;
 
     CLIENT.set(client).expect("CLIENT already initialized");
+    FEED_CLIENT
+        .set(feed_client)
+        .expect("FEED_CLIENT already initialized");
+    MAX_REDIRECTS
+        .set(opt.max_redirects)
+        .expect("MAX_REDIRECTS already initialized");
+    READ_TIMEOUT
+        .set(Duration::from_secs(opt.read_timeout))
+        .expect("READ_TIMEOUT already initialized");
+    REQUEST_TIMEOUT
+        .set(Duration::from_secs(opt.request_timeout))
+        .expect("REQUEST_TIMEOUT already initialized");
     RESP_SIZE_LIMIT
-        .set(max_feed_size)
+        .set(opt.max_feed_size)
         .expect("RESP_SIZE_LIMIT already initialized");
+    let limiter = limiter::FetchLimiter::new(limiter::FetchLimits {
+        max_in_flight: opt.max_fetches,
+        host_concurrency: opt.max_host_fetches,
+        host_requests_per_minute: opt.max_host_requests,
+    });
+    if LIMITER.set(limiter).is_err() {
+        panic!("LIMITER already initialized");
+    }
+    Ok(())
+}
+
+/// Media that can't be a feed, not even a mislabeled one
+fn content_type_is_unsupported(value: &HeaderValue) -> bool {
+    let mime = value
+        .to_str()
+        .unwrap_or_default()
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    ["image/", "audio/", "video/", "font/"]
+        .iter()
+        .any(|prefix| mime.starts_with(prefix))
+        || mime == "application/pdf"
+        || mime == "application/zip"
 }
 
 fn content_type_is_json(value: &HeaderValue) -> bool {
@@ -117,11 +860,22 @@ fn content_type_is_json(value: &HeaderValue) -> bool {
             value
                 .split(';')
                 .map(|v| v.trim())
-                .any(|v| v == "application/json")
+                .any(|v| v == "application/json" || v == "application/feed+json")
         })
         .unwrap_or(false)
 }
 
+fn content_type_charset(value: &HeaderValue) -> Option<String> {
+    value.to_str().ok()?.split(';').find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        if name.trim().eq_ignore_ascii_case("charset") {
+            Some(value.trim().trim_matches('"').to_owned())
+        } else {
+            None
+        }
+    })
+}
+
 /// About the "kiB" not "KiB": https://en.wikipedia.org/wiki/Metric_prefix#List_of_SI_prefixes
 fn format_byte_size(bytes: u64) -> String {
     const SIZES: [&str; 7] = ["B", "kiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
@@ -146,6 +900,25 @@ fn format_byte_size(bytes: u64) -> String {
     format!("{:.0}{}", (bytes / divisor), SIZES[i as usize])
 }
 
+#[cfg(test)]
+pub fn init_test_client() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        // Test servers are all on 127.0.0.1
+        let opt = <crate::Opt as clap::Parser>::parse_from([
+            "rssbot",
+            "test_token",
+            "--max-host-fetches",
+            "0",
+            "--max-host-requests",
+            "0",
+            "--request-timeout",
+            "2",
+        ]);
+        init_client("test_bot", &opt).unwrap();
+    });
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -171,4 +944,515 @@ mod test {
 
         assert_eq!(format_byte_size(1024 + 10), "1kiB");
     }
+
+    #[test]
+    fn json_content_types() {
+        for v in [
+            "application/json",
+            "application/feed+json",
+            "application/feed+json; charset=utf-8",
+        ] {
+            assert!(content_type_is_json(&HeaderValue::from_static(v)), "{}", v);
+        }
+        for v in ["application/rss+xml", "text/html; charset=utf-8"] {
+            assert!(!content_type_is_json(&HeaderValue::from_static(v)), "{}", v);
+        }
+    }
+
+    #[test]
+    fn content_type_charsets() {
+        for (v, charset) in [
+            ("text/xml; charset=GBK", Some("GBK")),
+            ("text/xml;charset=\"Shift_JIS\"", Some("Shift_JIS")),
+            ("text/xml; Charset = windows-1251", Some("windows-1251")),
+            ("text/xml", None),
+        ] {
+            assert_eq!(
+                content_type_charset(&HeaderValue::from_static(v)).as_deref(),
+                charset
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn conditional_get() {
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Body, Request, Response, Server};
+
+        init_test_client();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let url = format!("http://{}/feed.xml", listener.local_addr().unwrap());
+        let server = Server::from_tcp(listener)
+            .unwrap()
+            .serve(make_service_fn(|_| async {
+                Ok::<_, std::convert::Infallible>(service_fn(|req: Request<Body>| async move {
+                    let resp = if req
+                        .headers()
+                        .get(IF_NONE_MATCH)
+                        .is_some_and(|v| v == "\"v1\"")
+                    {
+                        Response::builder()
+                            .status(StatusCode::NOT_MODIFIED)
+                            .body(Body::empty())
+                    } else {
+                        Response::builder()
+                            .header(ETAG, "\"v1\"")
+                            .header(LAST_MODIFIED, "Sat, 01 Jan 2000 00:00:00 GMT")
+                            .body(Body::from(
+                                r#"<rss version="2.0"><channel><title>t</title></channel></rss>"#,
+                            ))
+                    };
+                    Ok::<_, std::convert::Infallible>(resp.unwrap())
+                }))
+            }));
+        tokio::spawn(server);
+
+        let request = RequestOptions::default();
+        let validators = match pull_feed_if_modified(&url, &request, &Validators::default()).await {
+            Ok(Pulled::Modified(feed, validators, _)) => {
+                assert_eq!(feed.title, "t");
+                validators
+            }
+            _ => panic!("expected a modified feed"),
+        };
+        assert_eq!(
+            validators,
+            Validators {
+                etag: Some("\"v1\"".into()),
+                last_modified: Some("Sat, 01 Jan 2000 00:00:00 GMT".into()),
+            }
+        );
+        assert!(matches!(
+            pull_feed_if_modified(&url, &request, &validators).await,
+            Ok(Pulled::NotModified(_))
+        ));
+        assert_eq!(pull_feed(&url, &request).await.unwrap().title, "t");
+    }
+
+    #[tokio::test]
+    async fn malformed_feeds() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Body, Request, Response, Server};
+
+        static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+        init_test_client();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let url = format!("http://{}/feed.xml", listener.local_addr().unwrap());
+        let server = Server::from_tcp(listener)
+            .unwrap()
+            .serve(make_service_fn(|_| async {
+                Ok::<_, std::convert::Infallible>(service_fn(|req: Request<Body>| async move {
+                    REQUESTS.fetch_add(1, Ordering::SeqCst);
+                    let description = match req.uri().path() {
+                        "/large.xml" => "a".repeat(MAX_RECOVERY_LEN as usize),
+                        _ => String::new(),
+                    };
+                    Ok::<_, std::convert::Infallible>(Response::new(Body::from(format!(
+                        r#"<rss version="2.0"><channel><title>Q&A</title><description>{}</description></channel></rss>"#,
+                        description
+                    ))))
+                }))
+            }));
+        tokio::spawn(server);
+
+        let feed = pull_feed(&url, &RequestOptions::default()).await.unwrap();
+        assert_eq!(feed.title, "Q&A");
+        assert!(feed.recovered);
+        // Parsed again from the same response
+        assert_eq!(REQUESTS.load(Ordering::SeqCst), 1);
+        // Not kept in memory for recovery
+        let url = url.replace("feed.xml", "large.xml");
+        assert!(matches!(
+            pull_feed(&url, &RequestOptions::default()).await,
+            Err(FeedError::Parsing(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn compressed_feeds() {
+        use async_compression::tokio::bufread::{GzipEncoder, ZstdEncoder};
+        use hyper::body::Bytes;
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Body, Request, Response, Server};
+
+        async fn compress(mut encoder: impl tokio::io::AsyncRead + Unpin) -> Bytes {
+            let mut buf = Vec::new();
+            encoder.read_to_end(&mut buf).await.unwrap();
+            buf.into()
+        }
+        let feed = br#"<rss version="2.0"><channel><title>t</title></channel></rss>"#;
+        let gzip = compress(GzipEncoder::new(&feed[..])).await;
+        let zstd = compress(ZstdEncoder::new(&feed[..])).await;
+        let mut huge = b"<rss version=\"2.0\"><channel><title>".to_vec();
+        huge.resize(3 * 1024 * 1024, b'a');
+        let bomb = compress(GzipEncoder::new(&huge[..])).await;
+
+        init_test_client();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = Server::from_tcp(listener)
+            .unwrap()
+            .serve(make_service_fn(move |_| {
+                let (gzip, zstd, bomb) = (gzip.clone(), zstd.clone(), bomb.clone());
+                async move {
+                    Ok::<_, std::convert::Infallible>(service_fn(move |req: Request<Body>| {
+                        let resp = match req.uri().path() {
+                            "/feed.xml.gz" => Response::builder()
+                                .header(CONTENT_TYPE, "application/gzip")
+                                .body(Body::from(gzip.clone())),
+                            "/zstd.xml" => Response::builder()
+                                .header(CONTENT_ENCODING, "zstd")
+                                .body(Body::from(zstd.clone())),
+                            _ => Response::builder()
+                                .header(CONTENT_TYPE, "application/gzip")
+                                .body(Body::from(bomb.clone())),
+                        };
+                        async move { Ok::<_, std::convert::Infallible>(resp.unwrap()) }
+                    }))
+                }
+            }));
+        tokio::spawn(server);
+
+        let request = RequestOptions::default();
+        for path in ["/feed.xml.gz", "/zstd.xml"] {
+            let feed = pull_feed(&format!("{}{}", base, path), &request).await;
+            assert_eq!(feed.unwrap().title, "t", "{}", path);
+        }
+        assert!(matches!(
+            pull_feed(&format!("{}/bomb.xml.gz", base), &request).await,
+            Err(FeedError::TooLarge(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn error_classes() {
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{header::LOCATION, Body, Request, Response, Server};
+
+        init_test_client();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = Server::from_tcp(listener)
+            .unwrap()
+            .serve(make_service_fn(|_| async {
+                Ok::<_, std::convert::Infallible>(service_fn(|req: Request<Body>| async move {
+                    let resp = Response::builder();
+                    let resp = match req.uri().path() {
+                        "/404" => resp.status(StatusCode::NOT_FOUND).body(Body::empty()),
+                        "/502" => resp.status(StatusCode::BAD_GATEWAY).body(Body::empty()),
+                        "/loop" => resp
+                            .status(StatusCode::FOUND)
+                            .header(LOCATION, "/loop")
+                            .body(Body::empty()),
+                        "/image" => resp
+                            .header(CONTENT_TYPE, "image/png")
+                            .body(Body::from("\u{89}PNG")),
+                        _ => resp.body(Body::empty()),
+                    };
+                    Ok::<_, std::convert::Infallible>(resp.unwrap())
+                }))
+            }));
+        tokio::spawn(server);
+
+        let request = RequestOptions::default();
+        let pull = |path: &str| {
+            let url = format!("{}{}", base, path);
+            async move {
+                pull_feed(&url, &RequestOptions::default())
+                    .await
+                    .unwrap_err()
+            }
+        };
+        let e = pull("/404").await;
+        assert!(matches!(e, FeedError::Status(StatusCode::NOT_FOUND)));
+        assert_eq!(e.retry(), Retry::Backoff);
+        let e = pull("/502").await;
+        assert!(matches!(e, FeedError::Status(StatusCode::BAD_GATEWAY)));
+        assert_eq!(e.retry(), Retry::Normal);
+        assert!(matches!(pull("/loop").await, FeedError::TooManyRedirects));
+        assert!(matches!(
+            pull("/image").await,
+            FeedError::UnsupportedContentType(t) if t == "image/png"
+        ));
+        assert!(matches!(pull("/empty").await, FeedError::EmptyDocument));
+        let e = pull_feed("http://rssbot.invalid/feed.xml", &request)
+            .await
+            .unwrap_err();
+        assert!(matches!(e, FeedError::Dns(_)), "{:?}", e);
+        assert_eq!(e.retry(), Retry::Backoff);
+    }
+
+    #[tokio::test]
+    async fn permanent_redirects() {
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Body, Request, Response, Server};
+
+        init_test_client();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = Server::from_tcp(listener)
+            .unwrap()
+            .serve(make_service_fn(|_| async {
+                Ok::<_, std::convert::Infallible>(service_fn(|req: Request<Body>| async move {
+                    let redirect = |status, location| {
+                        Response::builder()
+                            .status(status)
+                            .header(LOCATION, location)
+                            .body(Body::empty())
+                    };
+                    let resp = match req.uri().path() {
+                        "/301" => redirect(StatusCode::MOVED_PERMANENTLY, "/308"),
+                        "/308" => redirect(StatusCode::PERMANENT_REDIRECT, "/feed.xml"),
+                        "/302" => redirect(StatusCode::FOUND, "/feed.xml"),
+                        "/mixed" => redirect(StatusCode::MOVED_PERMANENTLY, "/302"),
+                        "/gone" => Response::builder()
+                            .status(StatusCode::GONE)
+                            .body(Body::empty()),
+                        _ => Response::builder().body(Body::from(
+                            r#"<rss version="2.0"><channel><title>t</title></channel></rss>"#,
+                        )),
+                    };
+                    Ok::<_, std::convert::Infallible>(resp.unwrap())
+                }))
+            }));
+        tokio::spawn(server);
+
+        let request = RequestOptions::default();
+        let validators = Validators::default();
+        let pull = |path: &str| {
+            let url = format!("{}{}", base, path);
+            let (request, validators) = (&request, &validators);
+            async move { pull_feed_if_modified(&url, request, validators).await }
+        };
+        match pull("/301").await {
+            Ok(Pulled::Moved(url, pulled)) => {
+                assert_eq!(url, format!("{}/feed.xml", base));
+                assert!(matches!(*pulled, Pulled::Modified(..)));
+            }
+            _ => panic!("expected a moved feed"),
+        }
+        for path in ["/302", "/mixed", "/feed.xml"] {
+            assert!(
+                matches!(pull(path).await, Ok(Pulled::Modified(..))),
+                "{}",
+                path
+            );
+        }
+        assert!(matches!(
+            pull("/gone").await,
+            Err(FeedError::Status(StatusCode::GONE))
+        ));
+        let url = format!("{}/301", base);
+        assert_eq!(pull_feed(&url, &request).await.unwrap().title, "t");
+    }
+
+    #[tokio::test]
+    async fn trickling_bodies() {
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Body, Request, Response, Server};
+
+        init_test_client();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let url = format!("http://{}/feed.xml", listener.local_addr().unwrap());
+        let server = Server::from_tcp(listener)
+            .unwrap()
+            .serve(make_service_fn(|_| async {
+                Ok::<_, std::convert::Infallible>(service_fn(|_: Request<Body>| async move {
+                    let (mut sender, body) = Body::channel();
+                    // A byte every 100ms, never reaching `--read-timeout`
+                    tokio::spawn(async move {
+                        while sender.send_data(" ".into()).await.is_ok() {
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                        }
+                    });
+                    Ok::<_, std::convert::Infallible>(Response::new(body))
+                }))
+            }));
+        tokio::spawn(server);
+
+        let start = Instant::now();
+        assert!(matches!(
+            pull_feed(&url, &RequestOptions::default()).await,
+            Err(FeedError::RequestTimeout)
+        ));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn cross_origin_redirects() {
+        use std::sync::atomic::{AtomicU16, Ordering};
+
+        use hyper::header::AUTHORIZATION;
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Body, Request, Response, Server};
+
+        static PORT: AtomicU16 = AtomicU16::new(0);
+        init_test_client();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        PORT.store(port, Ordering::SeqCst);
+        let server = Server::from_tcp(listener)
+            .unwrap()
+            .serve(make_service_fn(|_| async {
+                Ok::<_, std::convert::Infallible>(service_fn(|req: Request<Body>| async move {
+                    let port = PORT.load(Ordering::SeqCst);
+                    let authorized = req
+                        .headers()
+                        .get(AUTHORIZATION)
+                        .is_some_and(|v| v == "Bearer token");
+                    let resp = match req.uri().path() {
+                        // Same server, another origin
+                        "/private" if authorized => Response::builder()
+                            .status(StatusCode::MOVED_PERMANENTLY)
+                            .header(LOCATION, format!("http://localhost:{}/feed.xml", port))
+                            .body(Body::empty()),
+                        "/feed.xml" => Response::builder().body(Body::from(format!(
+                            r#"<rss version="2.0"><channel><title>{}</title></channel></rss>"#,
+                            authorized
+                        ))),
+                        _ => Response::builder()
+                            .status(StatusCode::UNAUTHORIZED)
+                            .body(Body::empty()),
+                    };
+                    Ok::<_, std::convert::Infallible>(resp.unwrap())
+                }))
+            }));
+        tokio::spawn(server);
+
+        let url = format!("http://127.0.0.1:{}/private", port);
+        let request = RequestOptions::parse(&["bearer:token"]).unwrap();
+        match pull_feed_if_modified(&url, &request, &Validators::default()).await {
+            Ok(Pulled::Moved(moved_to, pulled)) => {
+                assert_eq!(moved_to, format!("http://localhost:{}/feed.xml", port));
+                match *pulled {
+                    // The token isn't sent to the new site
+                    Pulled::Modified(feed, ..) => assert_eq!(feed.title, "false"),
+                    _ => panic!("expected a modified feed"),
+                }
+            }
+            _ => panic!("expected a moved feed"),
+        }
+    }
+
+    #[test]
+    fn freshness_headers() {
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            let mut headers = HeaderMap::new();
+            for (name, value) in pairs {
+                headers.insert(*name, HeaderValue::from_static(value));
+            }
+            headers
+        };
+        let secs = Duration::from_secs;
+        assert_eq!(
+            freshness(&headers(&[
+                ("cache-control", "public, max-age=600"),
+                ("age", "100"),
+                ("expires", "Sat, 01 Jan 2000 01:00:00 GMT"),
+            ])),
+            Some(Freshness::MaxAge(secs(500)))
+        );
+        assert_eq!(
+            freshness(&headers(&[
+                ("date", "Sat, 01 Jan 2000 00:00:00 GMT"),
+                ("expires", "Sat, 01 Jan 2000 01:00:00 GMT"),
+            ])),
+            Some(Freshness::Expires(secs(3600)))
+        );
+        assert_eq!(
+            freshness(&headers(&[("expires", "0")])),
+            Some(Freshness::Expires(secs(0)))
+        );
+        assert_eq!(freshness(&headers(&[("cache-control", "no-cache")])), None);
+    }
+
+    #[test]
+    fn retry_after_values() {
+        assert_eq!(
+            retry_after(&HeaderValue::from_static("120")),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            retry_after(&HeaderValue::from_static("Sat, 01 Jan 2000 00:00:00 GMT")),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(retry_after(&HeaderValue::from_static("soon")), None);
+    }
+
+    #[test]
+    fn request_options() {
+        let args = [
+            "header:X-Api-Key=abc%20def",
+            "cookie:session=1",
+            "cookie:theme=dark",
+            "basic:alice:p:w",
+        ];
+        assert!(args.iter().all(|arg| RequestOptions::is_option(arg)));
+        assert!(!RequestOptions::is_option("https://example.com"));
+        let options = RequestOptions::parse(&args).unwrap();
+        assert_eq!(
+            options,
+            RequestOptions {
+                headers: vec![("X-Api-Key".into(), "abc def".into())],
+                cookies: vec![
+                    ("session".into(), "1".into()),
+                    ("theme".into(), "dark".into())
+                ],
+                auth: Some(Auth::Basic {
+                    username: "alice".into(),
+                    password: Some("p:w".into())
+                }),
+            }
+        );
+        assert!(!format!("{:?}", options).contains("abc"));
+        assert_eq!(
+            RequestOptions::parse(&["bearer:token"]).unwrap().auth,
+            Some(Auth::Bearer("token".into()))
+        );
+        assert!(RequestOptions::parse(&["header:Bad Name=1"]).is_none());
+        assert!(RequestOptions::parse(&["header:X-Newline=a%0Ab"]).is_none());
+        assert!(RequestOptions::parse(&["header:"]).is_none());
+        assert!(RequestOptions::parse(&["header:X-Token"]).is_none());
+        assert!(RequestOptions::parse(&["cookie:=1"]).is_none());
+        let options = RequestOptions::parse(&[
+            "header:X-Token=a+b/c==",
+            "cookie:sid=x&y",
+            "basic:a%3Ab:100%25",
+        ])
+        .unwrap();
+        assert_eq!(options.headers, vec![("X-Token".into(), "a+b/c==".into())]);
+        assert_eq!(options.cookies, vec![("sid".into(), "x&y".into())]);
+        assert_eq!(
+            options.auth,
+            Some(Auth::Basic {
+                username: "a:b".into(),
+                password: Some("100%".into())
+            })
+        );
+    }
+
+    #[test]
+    fn origins() {
+        assert!(same_origin(
+            "https://example.com/feed",
+            "https://example.com/feed?page=2"
+        ));
+        assert!(!same_origin(
+            "https://example.com/feed",
+            "http://example.com/feed"
+        ));
+        assert!(!same_origin(
+            "https://example.com/feed",
+            "https://cdn.example.com/feed"
+        ));
+    }
 }
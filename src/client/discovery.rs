@@ -0,0 +1,240 @@
+//! Finding feeds of an HTML page.
+//!
+//! Feeds advertised by `<link rel="alternate">` are preferred, common
+//! paths like `/feed` are only tried when there is none.
+
+use lazy_static::lazy_static;
+use regex::{bytes::Regex as BytesRegex, Regex};
+use reqwest::header::HeaderValue;
+use url::Url;
+
+use super::{pull_feed, RequestOptions};
+
+/// `<link>` tags are in `<head>`, no need to read the whole page
+pub const MAX_HTML_LEN: u64 = 512 * 1024;
+const FEED_TYPES: [&str; 3] = [
+    "application/rss+xml",
+    "application/atom+xml",
+    "application/feed+json",
+];
+const COMMON_PATHS: [&str; 6] = [
+    "/feed",
+    "/rss",
+    "/feed.xml",
+    "/rss.xml",
+    "/atom.xml",
+    "/index.xml",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedLink {
+    pub url: String,
+    pub title: Option<String>,
+}
+
+pub fn is_html(value: &HeaderValue) -> bool {
+    value
+        .to_str()
+        .map(|value| {
+            let mime = value.split(';').next().unwrap_or_default().trim();
+            mime.eq_ignore_ascii_case("text/html")
+                || mime.eq_ignore_ascii_case("application/xhtml+xml")
+        })
+        .unwrap_or(false)
+}
+
+/// Whether the beginning of a `text/html` response is actually a feed
+pub fn looks_like_feed(head: &[u8]) -> bool {
+    lazy_static! {
+        static ref ROOT: BytesRegex =
+            BytesRegex::new(r"(?i)<(rss|feed|rdf:rdf|html)[\s>]").unwrap();
+    }
+    matches!(ROOT.captures(head), Some(root) if !root[1].eq_ignore_ascii_case(b"html"))
+}
+
+/// Feeds of the page at `page_url`, `html` is the beginning of the page.
+/// Common paths are fetched with `request`, only call it when subscribing.
+pub async fn discover(page_url: &str, html: &[u8], request: &RequestOptions) -> Vec<FeedLink> {
+    let page_url = match Url::parse(page_url) {
+        Ok(url) => url,
+        Err(_) => return Vec::new(),
+    };
+    let links = find_feed_links(&String::from_utf8_lossy(html), &page_url);
+    if !links.is_empty() {
+        return links;
+    }
+    for path in COMMON_PATHS {
+        let url = match page_url.join(path) {
+            Ok(url) => url,
+            Err(_) => continue,
+        };
+        // Errors only mean there is no feed at this path
+        if pull_feed(url.as_str(), request).await.is_ok() {
+            return vec![FeedLink {
+                url: url.into(),
+                title: None,
+            }];
+        }
+    }
+    Vec::new()
+}
+
+fn find_feed_links(html: &str, page_url: &Url) -> Vec<FeedLink> {
+    lazy_static! {
+        static ref LINK: Regex = Regex::new(r"(?i)<link\s([^>]*)>").unwrap();
+        static ref ATTR: Regex =
+            Regex::new(r#"([A-Za-z-]+)\s*=\s*(?:"([^"]*)"|'([^']*)'|([^\s"'>]+))"#).unwrap();
+    }
+    let mut links: Vec<FeedLink> = Vec::new();
+    for tag in LINK.captures_iter(html) {
+        let (mut rel, mut kind, mut href, mut title) = (None, None, None, None);
+        for attr in ATTR.captures_iter(&tag[1]) {
+            let value = attr
+                .get(2)
+                .or_else(|| attr.get(3))
+                .or_else(|| attr.get(4))
+                .map(|value| unescape(value.as_str()));
+            match attr[1].to_ascii_lowercase().as_str() {
+                "rel" => rel = value,
+                "type" => kind = value,
+                "href" => href = value,
+                "title" => title = value,
+                _ => (),
+            }
+        }
+        let is_alternate = rel.is_some_and(|rel| {
+            rel.split_ascii_whitespace()
+                .any(|rel| rel.eq_ignore_ascii_case("alternate"))
+        });
+        let is_feed = kind.is_some_and(|kind| {
+            FEED_TYPES
+                .iter()
+                .any(|feed_type| kind.trim().eq_ignore_ascii_case(feed_type))
+        });
+        let url = match href.and_then(|href| page_url.join(href.trim()).ok()) {
+            Some(url) if is_alternate && is_feed => String::from(url),
+            _ => continue,
+        };
+        if links.iter().all(|link| link.url != url) {
+            links.push(FeedLink {
+                url,
+                title: title.filter(|title| !title.trim().is_empty()),
+            });
+        }
+    }
+    links
+}
+
+fn unescape(s: &str) -> String {
+    quick_xml::escape::unescape(s)
+        .map(|s| s.into_owned())
+        .unwrap_or_else(|_| s.to_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn feed_links() {
+        let html = r#"<!DOCTYPE html>
+<html><head>
+<link rel="stylesheet" href="/style.css">
+<LINK REL="alternate" TYPE="application/rss+xml" TITLE="Posts" HREF="/feed.xml">
+<link rel='alternate' type='application/atom+xml' href='atom.xml?a=1&amp;b=2' title=''>
+<link rel=alternate type=application/feed+json href=//cdn.example.com/feed.json />
+<link rel="alternate" type="application/rss+xml" href="/feed.xml" title="Duplicated">
+<link rel="alternate" hreflang="zh" href="/zh/">
+<link rel="alternate" type="text/html" href="/mobile/">
+</head></html>"#;
+        let page = Url::parse("https://example.com/blog/").unwrap();
+        assert_eq!(
+            find_feed_links(html, &page),
+            vec![
+                FeedLink {
+                    url: "https://example.com/feed.xml".into(),
+                    title: Some("Posts".into()),
+                },
+                FeedLink {
+                    url: "https://example.com/blog/atom.xml?a=1&b=2".into(),
+                    title: None,
+                },
+                FeedLink {
+                    url: "https://cdn.example.com/feed.json".into(),
+                    title: None,
+                },
+            ]
+        );
+        assert!(find_feed_links("<html></html>", &page).is_empty());
+    }
+
+    #[tokio::test]
+    async fn common_paths() {
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Body, Request, Response, Server, StatusCode};
+
+        super::super::init_test_client();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let page = format!("http://{}/blog/", listener.local_addr().unwrap());
+        let server = Server::from_tcp(listener)
+            .unwrap()
+            .serve(make_service_fn(|_| async {
+                Ok::<_, std::convert::Infallible>(service_fn(|req: Request<Body>| async move {
+                    let resp = match req.uri().path() {
+                        // Not HTML, but not a feed either
+                        "/feed" => Response::builder()
+                            .header("Content-Type", "text/plain")
+                            .body(Body::from("Nothing here")),
+                        "/feed.xml" => Response::builder().body(Body::from(
+                            r#"<rss version="2.0"><channel><title>t</title></channel></rss>"#,
+                        )),
+                        _ => Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .body(Body::empty()),
+                    };
+                    Ok::<_, std::convert::Infallible>(resp.unwrap())
+                }))
+            }));
+        tokio::spawn(server);
+
+        let links = discover(&page, b"<html></html>", &RequestOptions::default()).await;
+        assert_eq!(
+            links,
+            vec![FeedLink {
+                url: Url::parse(&page).unwrap().join("/feed.xml").unwrap().into(),
+                title: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn feeds_as_html() {
+        assert!(looks_like_feed(
+            b"<?xml version=\"1.0\"?>\n<rss version=\"2.0\">"
+        ));
+        assert!(looks_like_feed(
+            b"<feed xmlns=\"http://www.w3.org/2005/Atom\">"
+        ));
+        assert!(looks_like_feed(b"<?xml version=\"1.0\"?><rdf:RDF>"));
+        assert!(!looks_like_feed(b"<!DOCTYPE html><html><head>"));
+        assert!(!looks_like_feed(
+            b"<?xml version=\"1.0\"?><html xmlns=\"http://www.w3.org/1999/xhtml\">"
+        ));
+        assert!(!looks_like_feed(b""));
+    }
+
+    #[test]
+    fn html_content_types() {
+        for v in [
+            "text/html",
+            "text/html; charset=utf-8",
+            "application/xhtml+xml",
+        ] {
+            assert!(is_html(&HeaderValue::from_static(v)), "{}", v);
+        }
+        for v in ["application/rss+xml", "text/xml"] {
+            assert!(!is_html(&HeaderValue::from_static(v)), "{}", v);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Limits of requests to feed hosts.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::{
+    sync::{Mutex, OwnedSemaphorePermit, Semaphore},
+    time::{self, Duration, Instant},
+};
+use url::Url;
+
+/// 0 is unlimited
+#[derive(Debug, Clone, Copy)]
+pub struct FetchLimits {
+    pub max_in_flight: usize,
+    pub host_concurrency: usize,
+    pub host_requests_per_minute: u32,
+}
+
+/// Caps concurrent requests globally and per host, and spaces out
+/// requests to the same host, many feeds can live on one host.
+/// Every request is charged, including redirects and discovery probes
+pub struct FetchLimiter {
+    in_flight: Option<Arc<Semaphore>>,
+    host_concurrency: usize,
+    host_interval: Option<Duration>,
+    hosts: std::sync::Mutex<HashMap<String, Arc<HostLimiter>>>,
+}
+
+struct HostLimiter {
+    concurrency: Option<Arc<Semaphore>>,
+    next_slot: Mutex<Instant>,
+}
+
+#[must_use = "The limit is released once dropped"]
+pub struct FetchPermit {
+    _host: Option<OwnedSemaphorePermit>,
+    _in_flight: Option<OwnedSemaphorePermit>,
+}
+
+impl FetchLimiter {
+    pub fn new(limits: FetchLimits) -> Self {
+        let semaphore = |permits| (permits > 0).then(|| Arc::new(Semaphore::new(permits)));
+        FetchLimiter {
+            in_flight: semaphore(limits.max_in_flight),
+            host_concurrency: limits.host_concurrency,
+            host_interval: (limits.host_requests_per_minute > 0)
+                .then(|| Duration::from_secs(60) / limits.host_requests_per_minute),
+            hosts: Default::default(),
+        }
+    }
+
+    pub async fn acquire(&self, url: &str) -> FetchPermit {
+        async fn permit(semaphore: &Option<Arc<Semaphore>>) -> Option<OwnedSemaphorePermit> {
+            match semaphore {
+                Some(semaphore) => Some(
+                    semaphore
+                        .clone()
+                        .acquire_owned()
+                        .await
+                        .expect("semaphore closed"),
+                ),
+                None => None,
+            }
+        }
+
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_owned))
+            .unwrap_or_default();
+        let host = self
+            .hosts
+            .lock()
+            .unwrap()
+            .entry(host)
+            .or_insert_with(|| {
+                Arc::new(HostLimiter {
+                    concurrency: (self.host_concurrency > 0)
+                        .then(|| Arc::new(Semaphore::new(self.host_concurrency))),
+                    next_slot: Mutex::new(Instant::now()),
+                })
+            })
+            .clone();
+
+        // Wait for the host first, a busy host shouldn't hold global permits
+        let host_permit = permit(&host.concurrency).await;
+        if let Some(interval) = self.host_interval {
+            let slot = {
+                let mut next_slot = host.next_slot.lock().await;
+                let slot = cmp::max(*next_slot, Instant::now());
+                *next_slot = slot + interval;
+                slot
+            };
+            time::sleep_until(slot).await;
+        }
+        FetchPermit {
+            _host: host_permit,
+            _in_flight: permit(&self.in_flight).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn fetch_limits() {
+        let limiter = FetchLimiter::new(FetchLimits {
+            max_in_flight: 2,
+            host_concurrency: 1,
+            host_requests_per_minute: 0,
+        });
+        let blocked = |permit| async {
+            time::timeout(Duration::from_millis(50), permit)
+                .await
+                .is_err()
+        };
+        let a = limiter.acquire("https://a.example.com/1").await;
+        assert!(blocked(limiter.acquire("https://a.example.com/2")).await);
+        let b = limiter.acquire("https://b.example.com/1").await;
+        // Global limit
+        assert!(blocked(limiter.acquire("https://c.example.com/1")).await);
+        drop(a);
+        let _a = limiter.acquire("https://a.example.com/2").await;
+        drop(b);
+        let _c = limiter.acquire("https://c.example.com/1").await;
+    }
+
+    #[tokio::test]
+    async fn host_request_rate() {
+        let limiter = FetchLimiter::new(FetchLimits {
+            max_in_flight: 0,
+            host_concurrency: 0,
+            host_requests_per_minute: 600,
+        });
+        let start = Instant::now();
+        for _ in 0..3 {
+            let _permit = limiter.acquire("https://example.com/feed").await;
+        }
+        let _other = limiter.acquire("https://other.example.com/feed").await;
+        let elapsed = start.elapsed();
+        // 100ms between requests to the same host
+        assert!(elapsed >= Duration::from_millis(200), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(300), "{:?}", elapsed);
+    }
+}
@@ -0,0 +1,208 @@
+//! Feeds that aren't fetched over HTTP.
+//!
+//! `file://` URLs are read from the directories given by `--local-feed-dir`,
+//! `exec:<name>` runs the command configured by `--exec-feed <name>=<command>`
+//! and reads its stdout. Users can only pick from what is configured, they
+//! never provide paths outside of the directories or commands themselves.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+use once_cell::sync::OnceCell;
+use reqwest::header::HeaderValue;
+use tokio::io::AsyncReadExt;
+use url::Url;
+
+use super::FeedError;
+use crate::feed::Rss;
+
+static SOURCES: OnceCell<LocalSources> = OnceCell::new();
+
+#[derive(Debug, Default)]
+struct LocalSources {
+    dirs: Vec<PathBuf>,
+    commands: HashMap<String, Vec<String>>,
+    timeout: Duration,
+}
+
+pub fn init(dirs: &[PathBuf], commands: &[(String, Vec<String>)], timeout: Duration) {
+    let sources = LocalSources {
+        // Paths are compared after resolving symlinks
+        dirs: dirs
+            .iter()
+            .filter_map(|dir| match dir.canonicalize() {
+                Ok(dir) => Some(dir),
+                Err(e) => {
+                    eprintln!("Warning: local feed dir {}: {}", dir.display(), e);
+                    None
+                }
+            })
+            .collect(),
+        commands: commands.iter().cloned().collect(),
+        timeout,
+    };
+    SOURCES.set(sources).expect("SOURCES already initialized");
+}
+
+/// Whether `url` is a `file://` or `exec:` source, they are for bot admins only
+pub fn is_local(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((scheme, _)) => {
+            scheme.eq_ignore_ascii_case("file") || scheme.eq_ignore_ascii_case("exec")
+        }
+        None => false,
+    }
+}
+
+pub async fn pull(url: &str, size_limit: u64) -> Result<Rss, FeedError> {
+    let sources = SOURCES.get().ok_or(FeedError::SourceNotAllowed)?;
+    let body = match url.split_once(':') {
+        Some((scheme, name)) if scheme.eq_ignore_ascii_case("exec") => {
+            let command = sources
+                .commands
+                .get(name)
+                .ok_or(FeedError::SourceNotAllowed)?;
+            run(command, sources.timeout, size_limit).await?
+        }
+        _ => {
+            let path = Url::parse(url)
+                .ok()
+                .and_then(|url| url.to_file_path().ok())
+                .ok_or(FeedError::SourceNotAllowed)?;
+            read_file(&sources.dirs, &path, size_limit).await?
+        }
+    };
+    if body.iter().all(u8::is_ascii_whitespace) {
+        return Err(FeedError::EmptyDocument);
+    }
+    // There is no `Content-Type`, JSON feeds are objects
+    let first = body.iter().find(|b| !b.is_ascii_whitespace());
+    let content_type = (first == Some(&b'{')).then(|| HeaderValue::from_static("application/json"));
+    super::parse_feed_body(url, content_type.as_ref(), &body)
+}
+
+async fn read_file(dirs: &[PathBuf], path: &Path, size_limit: u64) -> Result<Vec<u8>, FeedError> {
+    let path = tokio::fs::canonicalize(path).await?;
+    if !dirs.iter().any(|dir| path.starts_with(dir)) {
+        return Err(FeedError::SourceNotAllowed);
+    }
+    let file = tokio::fs::File::open(&path).await?;
+    read_limited(file, size_limit).await
+}
+
+async fn run(command: &[String], timeout: Duration, size_limit: u64) -> Result<Vec<u8>, FeedError> {
+    let (program, args) = command.split_first().ok_or(FeedError::SourceNotAllowed)?;
+    let mut child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        // Error messages of the command end up in the log of the bot
+        .stderr(Stdio::inherit())
+        .kill_on_drop(true)
+        .spawn()?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let output = async {
+        let body = read_limited(stdout, size_limit).await?;
+        let status = child.wait().await?;
+        if !status.success() {
+            return Err(FeedError::CommandFailed(status));
+        }
+        Ok(body)
+    };
+    // The command is killed once `child` is dropped
+    match tokio::time::timeout(timeout, output).await {
+        Ok(result) => result,
+        Err(_) => Err(FeedError::CommandTimeout),
+    }
+}
+
+/// 0 is unlimited
+async fn read_limited(
+    reader: impl tokio::io::AsyncRead + Unpin,
+    size_limit: u64,
+) -> Result<Vec<u8>, FeedError> {
+    let mut body = Vec::new();
+    if size_limit == 0 {
+        let mut reader = reader;
+        reader.read_to_end(&mut body).await?;
+    } else {
+        reader.take(size_limit + 1).read_to_end(&mut body).await?;
+        if body.len() as u64 > size_limit {
+            return Err(FeedError::TooLarge(size_limit));
+        }
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn local_urls() {
+        assert!(is_local("file:///srv/feeds/status.xml"));
+        assert!(is_local("FILE:///srv/feeds/status.xml"));
+        assert!(is_local("exec:status"));
+        assert!(!is_local("https://example.com/exec:status"));
+        assert!(!is_local("status"));
+    }
+
+    #[tokio::test]
+    async fn local_sources() {
+        let dir = std::env::temp_dir().join(format!("rssbot-local-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("feeds")).unwrap();
+        let feed = r#"<rss version="2.0"><channel><title>t</title></channel></rss>"#;
+        std::fs::write(dir.join("feeds/feed.xml"), feed).unwrap();
+        std::fs::write(dir.join("secret.xml"), feed).unwrap();
+        let sh = |script: &str| vec!["sh".to_owned(), "-c".to_owned(), script.to_owned()];
+        init(
+            &[dir.join("feeds")],
+            &[
+                ("echo".into(), sh(&format!("echo '{}'", feed))),
+                (
+                    "json".into(),
+                    sh(
+                        r#"echo '{"version": "https://jsonfeed.org/version/1.1", "title": "j", "items": []}'"#,
+                    ),
+                ),
+                ("fail".into(), sh("exit 3")),
+                ("slow".into(), sh("sleep 5")),
+                ("huge".into(), sh("yes")),
+            ],
+            Duration::from_millis(500),
+        );
+        let url = |path: &Path| Url::from_file_path(path).unwrap().to_string();
+
+        let rss = pull(&url(&dir.join("feeds/feed.xml")), 1024).await.unwrap();
+        assert_eq!(rss.title, "t");
+        for path in ["feeds/../secret.xml", "secret.xml", "feeds/missing.xml"] {
+            assert!(pull(&url(&dir.join(path)), 1024).await.is_err(), "{}", path);
+        }
+        assert!(matches!(
+            pull(&url(&dir.join("feeds/feed.xml")), 10).await,
+            Err(FeedError::TooLarge(10))
+        ));
+
+        assert_eq!(pull("exec:echo", 1024).await.unwrap().title, "t");
+        assert_eq!(pull("exec:json", 1024).await.unwrap().title, "j");
+        assert!(matches!(
+            pull("exec:unknown", 1024).await,
+            Err(FeedError::SourceNotAllowed)
+        ));
+        assert!(matches!(
+            pull("exec:fail", 1024).await,
+            Err(FeedError::CommandFailed(_))
+        ));
+        assert!(matches!(
+            pull("exec:slow", 1024).await,
+            Err(FeedError::CommandTimeout)
+        ));
+        assert!(matches!(
+            pull("exec:huge", 1024).await,
+            Err(FeedError::TooLarge(1024))
+        ));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -48,10 +48,10 @@ pub async fn handle_command(
     }
     match cmd {
         Command::Start => start::start(bot, msg).await,
-        Command::Sub => sub::sub(bot, msg, db).await,
+        Command::Sub => sub::sub(bot, msg, db, opt).await,
         Command::Unsub => unsub::unsub(bot, msg, db).await,
         Command::Export => export::export(bot, msg, db).await,
-        Command::Rss => rss::rss(bot, msg, db).await,
+        Command::Rss => rss::rss(bot, msg, db, opt).await,
     }
 }
 
@@ -213,6 +213,8 @@ async fn update_response(
     let msg = if *first_time {
         let mut send = bot.send_message(*chat_id, msg);
         send.reply_to_message_id = Some(*message_id);
+        // The command may be deleted already, e.g. it contains credentials
+        send.allow_sending_without_reply = Some(true);
         send.disable_web_page_preview = Some(true);
         send.parse_mode = mode;
         send.await?
@@ -282,3 +284,54 @@ async fn check_channel_permission(
     }
     Ok(Some(chat.id))
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn reply_to_deleted_messages() {
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Body, Request, Response, Server};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let api_url = format!("http://{}", listener.local_addr().unwrap());
+        // Telegram rejects replies to deleted messages unless asked not to
+        let server = Server::from_tcp(listener)
+            .unwrap()
+            .serve(make_service_fn(|_| async {
+                Ok::<_, std::convert::Infallible>(service_fn(|req: Request<Body>| async move {
+                    let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                    let params: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                    let resp = if params["allow_sending_without_reply"] == true {
+                        serde_json::json!({
+                            "ok": true,
+                            "result": {
+                                "message_id": 2,
+                                "date": 0,
+                                "chat": {"id": 1, "type": "private"},
+                                "text": params["text"],
+                            },
+                        })
+                    } else {
+                        serde_json::json!({
+                            "ok": false,
+                            "error_code": 400,
+                            "description": "Bad Request: message to be replied not found",
+                        })
+                    };
+                    Ok::<_, std::convert::Infallible>(Response::new(Body::from(resp.to_string())))
+                }))
+            }));
+        tokio::spawn(server);
+
+        let bot = Bot::new("test_token").set_api_url(api_url.parse().unwrap());
+        let target = &mut MsgTarget::new(ChatId(1), MessageId(1));
+        update_response(&bot, target, "processing", None)
+            .await
+            .unwrap();
+        assert_eq!(target.message_id, MessageId(2));
+        assert!(!target.first_time);
+    }
+}
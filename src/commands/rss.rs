@@ -10,11 +10,17 @@ use teloxide::Bot;
 use tokio::sync::Mutex;
 
 use crate::data::Database;
-use crate::messages::{format_large_msg, Escape};
+use crate::fetcher::schedule;
+use crate::messages::{format_duration, format_large_msg, truncate, Escape};
 
-use super::{check_channel_permission, update_response, MsgTarget};
+use super::{check_channel_permission, is_from_bot_admin, update_response, MsgTarget};
 
-pub async fn rss(bot: Bot, msg: Message, db: Arc<Mutex<Database>>) -> Result<(), anyhow::Error> {
+pub async fn rss(
+    bot: Bot,
+    msg: Message,
+    db: Arc<Mutex<Database>>,
+    opt: Arc<crate::Opt>,
+) -> Result<(), anyhow::Error> {
     let chat_id = msg.chat.id;
     let (_, args) = parse_command(
         msg.text().context("content of command text is empty")?,
@@ -46,12 +52,45 @@ pub async fn rss(bot: Bot, msg: Message, db: Arc<Mutex<Database>>) -> Result<(),
                 })
                 .collect::<Vec<Either<char, &str>>>()
         });
+        // Bot admins can see why a feed is fetched when it is,
+        // and what is wrong with broken feeds
+        let show_schedule = is_from_bot_admin(&msg, &opt.admin);
         format_large_msg(tr!("subscription_list").to_string(), &feeds, |feed| {
-            format!(
+            let mut line = format!(
                 "<a href=\"{}\">{}</a>",
                 Escape(&feed.link),
                 Escape(&feed.title)
-            )
+            );
+            if let Some(language) = feed.language.as_deref().map(str::trim) {
+                if !language.is_empty() {
+                    line.push_str(&format!(" [{}]", Escape(language)));
+                }
+            }
+            if show_schedule {
+                let (interval, reason) = schedule(feed, opt.min_interval, opt.max_interval);
+                line.push_str(&format!(
+                    " <i>{} ({})</i>",
+                    format_duration(interval),
+                    reason
+                ));
+                if feed.recovered {
+                    line.push_str(" <i>malformed</i>");
+                }
+                if !feed.warnings.is_empty() {
+                    let warnings: Vec<_> = feed.warnings.iter().map(|w| w.to_string()).collect();
+                    line.push_str(&format!(
+                        " <i>{} warnings: {}</i>",
+                        warnings.len(),
+                        Escape(&truncate(&warnings.join("; "), 100))
+                    ));
+                }
+            }
+            if let Some(description) = feed.description.as_deref().map(str::trim) {
+                if !description.is_empty() {
+                    line.push_str(&format!("\n<i>{}</i>", Escape(&truncate(description, 100))));
+                }
+            }
+            line
         })
     } else {
         vec![tr!("subscription_list_empty").to_string()]
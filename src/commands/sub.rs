@@ -1,19 +1,23 @@
 use std::sync::Arc;
 
 use anyhow::Context;
-use teloxide::{types::Message, utils::command::parse_command, Bot};
+use teloxide::{requests::Requester, types::Message, utils::command::parse_command, Bot};
 use tokio::sync::Mutex;
 
 use crate::data::Database;
-use crate::messages::Escape;
-use crate::{client::pull_feed, BOT_NAME};
+use crate::messages::{truncate, Escape};
+use crate::{
+    client::{discover, is_local, pull_feed, same_origin, FeedError, RequestOptions},
+    BOT_NAME,
+};
 
-use super::{check_channel_permission, update_response, MsgTarget};
+use super::{check_channel_permission, is_from_bot_admin, update_response, MsgTarget};
 
 pub async fn sub(
     bot: Bot,
     msg: Message,
     db: Arc<Mutex<Database>>,
+    opt: Arc<crate::Opt>,
 ) -> Result<(), anyhow::Error> {
     let chat_id = msg.chat.id;
     // let text = msg.text().unwrap_or("");
@@ -23,19 +27,24 @@ pub async fn sub(
         BOT_NAME.get().unwrap(),
     )
     .context("failed to parse command")?;
+    let (options, args): (Vec<&str>, Vec<&str>) = args
+        .into_iter()
+        .partition(|arg| RequestOptions::is_option(arg));
     let mut target_id = chat_id;
     let target = &mut MsgTarget::new(chat_id, msg.id);
-    let feed_url;
+    let mut feed_url;
+    let mut channel_arg = None;
 
     match &*args {
-        [url] => feed_url = url,
+        [url] => feed_url = url.to_string(),
         [channel, url] => {
             let channel_id = check_channel_permission(&bot, &msg, channel, target).await?;
             if channel_id.is_none() {
                 return Ok(());
             }
             target_id = channel_id.unwrap();
-            feed_url = url;
+            feed_url = url.to_string();
+            channel_arg = Some(channel);
         }
         [..] => {
             let msg = tr!("sub_how_to_use");
@@ -43,10 +52,28 @@ pub async fn sub(
             return Ok(());
         }
     };
-    if db.lock().await.is_subscribed(target_id.0, feed_url) {
+    let mut request = match RequestOptions::parse(&options) {
+        Some(request) => request,
+        None => {
+            let msg = tr!("sub_how_to_use");
+            update_response(&bot, target, msg, None).await?;
+            return Ok(());
+        }
+    };
+    if !request.is_empty() {
+        // Don't leave credentials in the chat history, best effort
+        let _ignore_result = bot.delete_message(chat_id, msg.id).await;
+    }
+    if db.lock().await.is_subscribed(target_id.0, &feed_url) {
         update_response(&bot, target, tr!("subscribed_to_rss"), None).await?;
         return Ok(());
     }
+    // Local feeds read files and run commands on the server
+    let is_bot_admin = is_from_bot_admin(&msg, &opt.admin);
+    if is_local(&feed_url) && !is_bot_admin {
+        update_response(&bot, target, tr!("local_feed_admin_only"), None).await?;
+        return Ok(());
+    }
 
     if cfg!(feature = "hosted-by-iovxw") && db.lock().await.all_feeds().len() >= 1500 {
         let msg = tr!("subscription_rate_limit");
@@ -60,18 +87,76 @@ pub async fn sub(
         return Ok(());
     }
     update_response(&bot, target, tr!("processing_please_wait"), None).await?;
-    let msg = match pull_feed(feed_url).await {
+    let mut result = pull_feed(&feed_url, &request).await;
+    let mut links = Vec::new();
+    if let Err(FeedError::HtmlPage(page_url, html)) = &result {
+        // Credentials are only for the site they are given to
+        let page_request = if same_origin(&feed_url, page_url) {
+            request.clone()
+        } else {
+            RequestOptions::default()
+        };
+        links = discover(page_url, html, &page_request).await;
+        // A web page with only one feed, subscribe to the feed instead
+        if let [link] = &links[..] {
+            if db.lock().await.is_subscribed(target_id.0, &link.url) {
+                update_response(&bot, target, tr!("subscribed_to_rss"), None).await?;
+                return Ok(());
+            }
+            if is_local(&link.url) && !is_bot_admin {
+                update_response(&bot, target, tr!("local_feed_admin_only"), None).await?;
+                return Ok(());
+            }
+            // Credentials are only for the site they are given to
+            if !same_origin(&feed_url, &link.url) {
+                request = RequestOptions::default();
+            }
+            feed_url = link.url.clone();
+            result = pull_feed(&feed_url, &request).await;
+        }
+    }
+    let msg = match result {
         Ok(feed) => {
-            if db.lock().await.subscribe(target_id.0, feed_url, &feed) {
-                tr!(
+            for warning in &feed.warnings {
+                eprintln!("Warning: {}: {}", feed_url, warning);
+            }
+            if db
+                .lock()
+                .await
+                .subscribe(target_id.0, &feed_url, &feed, &request)
+            {
+                let mut msg = tr!(
                     "subscription_succeeded",
                     link = Escape(&feed.link),
                     title = Escape(&feed.title)
-                )
+                );
+                if let Some(description) = feed.description.as_deref().map(str::trim) {
+                    if !description.is_empty() {
+                        msg.push('\n');
+                        msg.push_str(&format!("<i>{}</i>", Escape(&truncate(description, 200))));
+                    }
+                }
+                msg
             } else {
                 tr!("subscribed_to_rss").into()
             }
         }
+        Err(FeedError::HtmlPage(..)) if links.is_empty() => tr!("no_feed_found").into(),
+        Err(FeedError::HtmlPage(..)) if links.len() > 1 => {
+            let mut msg = tr!("multiple_feeds_found").to_owned();
+            for link in links {
+                let command = match channel_arg {
+                    Some(channel) => format!("/sub {} {}", channel, link.url),
+                    None => format!("/sub {}", link.url),
+                };
+                msg.push('\n');
+                if let Some(title) = link.title {
+                    msg.push_str(&format!("{}: ", Escape(&title)));
+                }
+                msg.push_str(&format!("<code>{}</code>", Escape(&command)));
+            }
+            msg
+        }
         Err(e) => tr!("subscription_failed", error = Escape(&e.to_user_friendly())),
     };
     update_response(&bot, target, &msg, Some(teloxide::types::ParseMode::Html)).await?;
@@ -6,6 +6,7 @@ use teloxide::{types::Message, utils::command::parse_command, Bot};
 use tokio::sync::Mutex;
 
 use crate::messages::Escape;
+use crate::websub;
 use crate::{data::Database, BOT_NAME};
 
 use super::{check_channel_permission, update_response, MsgTarget};
@@ -44,6 +45,10 @@ pub async fn unsub(
         }
     };
     let msg = if let Some(feed) = db.lock().await.unsubscribe(target_id.0, feed_url) {
+        // The feed was removed with its last subscriber
+        if let (true, Some(hub)) = (feed.subscribers.is_empty(), feed.hub.clone()) {
+            tokio::spawn(websub::unsubscribe(hub));
+        }
         tr!(
             "unsubscription_succeeded",
             link = Escape(&feed.link),
@@ -9,6 +9,7 @@ use serde::{Deserialize, Serialize};
 
 use thiserror::Error;
 
+use crate::client::{same_origin, Freshness, RequestOptions, Validators};
 use crate::feed;
 
 #[derive(Error, Debug)]
@@ -25,9 +26,24 @@ fn gen_hash<T: Hash>(t: &T) -> u64 {
     hasher.finish()
 }
 
-type FeedId = u64;
+pub type FeedId = u64;
 type SubscriberId = i64;
 
+/// Hashes of seen items kept beyond the size of the latest document,
+/// for feeds pushed with only the new items
+const MAX_HASH_HISTORY: usize = 500;
+
+/// Feeds of the same link with different request options are fetched
+/// separately, their subscribers may see different things.
+/// Ones without request options are identified by the link only
+pub fn feed_id(rss_link: &str, request: &RequestOptions) -> FeedId {
+    if request.is_empty() {
+        gen_hash(&rss_link)
+    } else {
+        gen_hash(&(rss_link, request))
+    }
+}
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Feed {
     pub link: String,
@@ -35,13 +51,59 @@ pub struct Feed {
     pub down_time: Option<SystemTime>,
     pub subscribers: HashSet<SubscriberId, Size64>,
     pub ttl: Option<u32>,
+    pub description: Option<String>,
+    pub icon: Option<String>,
+    pub language: Option<String>,
+    pub generator: Option<String>,
+    pub hub: Option<Hub>,
+    /// `ETag` of the last response
+    pub etag: Option<String>,
+    /// `Last-Modified` of the last response
+    pub last_modified: Option<String>,
+    /// Secrets, never show them to users
+    #[serde(default, skip_serializing_if = "RequestOptions::is_empty")]
+    pub request: RequestOptions,
+    /// Canonical URL of the feed by its self link, once it's been dealt with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub self_link: Option<String>,
+    /// When a self link that couldn't be verified was last checked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub self_link_checked: Option<SystemTime>,
+    #[serde(skip)]
+    pub freshness: Option<Freshness>,
+    /// Don't fetch before this, asked by the server
+    #[serde(skip)]
+    pub retry_after: Option<SystemTime>,
+    /// The last fetch failed with an error that won't go away soon
+    #[serde(skip)]
+    pub backoff: bool,
+    /// The last fetched document was malformed and parsed in recovery mode
+    #[serde(skip)]
+    pub recovered: bool,
+    /// Non-fatal problems of the last fetched document
+    #[serde(skip)]
+    pub warnings: Vec<feed::Warning>,
     hash_list: Vec<u64>,
 }
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+impl Feed {
+    pub fn id(&self) -> FeedId {
+        feed_id(&self.link, &self.request)
+    }
+}
+
+/// WebSub subscription of a feed
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Hub {
     pub callback: String,
     pub secret: String,
+    /// URL of the hub
+    pub url: String,
+    /// The topic URL subscribed to, usually the self link of the feed
+    pub topic: String,
+    /// `None` until the hub verified the subscription
+    pub lease_expires: Option<SystemTime>,
+    pub requested_at: SystemTime,
 }
 
 #[derive(Debug)]
@@ -73,7 +135,7 @@ impl Database {
             let mut subscribers = HashMap::with_hasher(Size64::default());
 
             for feed in feeds_list {
-                let feed_id = gen_hash(&feed.link);
+                let feed_id = feed.id();
                 for subscriber in &feed.subscribers {
                     let subscribed_feeds = subscribers
                         .entry(subscriber.to_owned())
@@ -97,6 +159,10 @@ impl Database {
         self.feeds.values().cloned().collect()
     }
 
+    pub fn get_feed(&self, feed_id: FeedId) -> Option<Feed> {
+        self.feeds.get(&feed_id).cloned()
+    }
+
     pub fn all_subscribers(&self) -> Vec<SubscriberId> {
         self.subscribers.keys().copied().collect()
     }
@@ -112,8 +178,7 @@ impl Database {
     }
 
     /// Return `None` if feed not found
-    pub fn get_or_update_down_time(&mut self, rss_link: &str) -> Option<Duration> {
-        let feed_id = gen_hash(&rss_link);
+    pub fn get_or_update_down_time(&mut self, feed_id: FeedId) -> Option<Duration> {
         let feed = self.feeds.get_mut(&feed_id)?;
         let now = SystemTime::now();
         if let Some(t) = feed.down_time {
@@ -124,8 +189,7 @@ impl Database {
         }
     }
 
-    pub fn reset_down_time(&mut self, rss_link: &str) -> bool {
-        let feed_id = gen_hash(&rss_link);
+    pub fn reset_down_time(&mut self, feed_id: FeedId) -> bool {
         self.feeds
             .get_mut(&feed_id)
             .map(|feed| {
@@ -135,14 +199,31 @@ impl Database {
     }
 
     pub fn is_subscribed(&self, subscriber: SubscriberId, rss_link: &str) -> bool {
+        self.subscribed_feed_id(subscriber, rss_link).is_some()
+    }
+
+    /// The feed of `rss_link` subscribed to, whatever request options it has
+    fn subscribed_feed_id(&self, subscriber: SubscriberId, rss_link: &str) -> Option<FeedId> {
         self.subscribers
-            .get(&subscriber)
-            .map(|feeds| feeds.contains(&gen_hash(&rss_link)))
-            .unwrap_or(false)
+            .get(&subscriber)?
+            .iter()
+            .copied()
+            .find(|feed_id| self.feeds[feed_id].link == rss_link)
     }
 
-    pub fn subscribe(&mut self, subscriber: SubscriberId, rss_link: &str, rss: &feed::Rss) -> bool {
-        let feed_id = gen_hash(&rss_link);
+    /// Return `false` if `rss_link` is already subscribed to,
+    /// with any request options
+    pub fn subscribe(
+        &mut self,
+        subscriber: SubscriberId,
+        rss_link: &str,
+        rss: &feed::Rss,
+        request: &RequestOptions,
+    ) -> bool {
+        if self.is_subscribed(subscriber, rss_link) {
+            return false;
+        }
+        let feed_id = feed_id(rss_link, request);
         {
             let subscribed_feeds = self
                 .subscribers
@@ -158,6 +239,21 @@ impl Database {
                 title: rss.title.to_owned(),
                 down_time: None,
                 ttl: rss.ttl,
+                description: rss.description.clone(),
+                icon: rss.icon.clone(),
+                language: rss.language.clone(),
+                generator: rss.generator.clone(),
+                hub: None,
+                etag: None,
+                last_modified: None,
+                request: request.clone(),
+                self_link: None,
+                self_link_checked: None,
+                freshness: None,
+                retry_after: None,
+                backoff: false,
+                recovered: rss.recovered,
+                warnings: rss.warnings.clone(),
                 hash_list: rss.items.iter().map(gen_item_hash).collect(),
                 subscribers: HashSet::default(),
             });
@@ -168,7 +264,7 @@ impl Database {
     }
 
     pub fn unsubscribe(&mut self, subscriber: SubscriberId, rss_link: &str) -> Option<Feed> {
-        let feed_id = gen_hash(&rss_link);
+        let feed_id = self.subscribed_feed_id(subscriber, rss_link)?;
 
         let clear_subscriber;
         if let Some(subscribed_feeds) = self.subscribers.get_mut(&subscriber) {
@@ -203,6 +299,74 @@ impl Database {
         Some(result)
     }
 
+    /// Remove a feed and all subscriptions to it.
+    /// Return `None` if feed not found
+    pub fn delete_feed(&mut self, feed_id: FeedId) -> Option<Feed> {
+        let feed = self.feeds.remove(&feed_id)?;
+        for subscriber in &feed.subscribers {
+            if let Some(subscribed_feeds) = self.subscribers.get_mut(subscriber) {
+                subscribed_feeds.remove(&feed_id);
+                if subscribed_feeds.is_empty() {
+                    self.subscribers.remove(subscriber);
+                }
+            }
+        }
+        self.save().unwrap_or_default();
+        Some(feed)
+    }
+
+    /// Move a feed to `new_link`, it's merged into the feed there with the
+    /// same `request` options if there is one already. `request` options
+    /// don't move to other sites.
+    /// Return the feed at `new_link`, `None` if feed not found
+    pub fn migrate_feed(&mut self, feed_id: FeedId, new_link: &str) -> Option<Feed> {
+        let feed = self.feeds.get(&feed_id)?;
+        // Credentials are only for the site they are given to
+        let request = if same_origin(&feed.link, new_link) {
+            feed.request.clone()
+        } else {
+            RequestOptions::default()
+        };
+        let new_id = self::feed_id(new_link, &request);
+        if feed_id == new_id {
+            return Some(feed.clone());
+        }
+        let mut feed = self.feeds.remove(&feed_id)?;
+        feed.request = request;
+        for subscriber in &feed.subscribers {
+            if let Some(subscribed_feeds) = self.subscribers.get_mut(subscriber) {
+                subscribed_feeds.remove(&feed_id);
+                subscribed_feeds.insert(new_id);
+            }
+        }
+        let result = match self.feeds.get_mut(&new_id) {
+            Some(existing) => {
+                existing.subscribers.extend(feed.subscribers);
+                // Items seen by either side aren't new to the other
+                for hash in feed.hash_list {
+                    if !existing.hash_list.contains(&hash) {
+                        existing.hash_list.push(hash);
+                    }
+                }
+                existing.clone()
+            }
+            None => {
+                feed.link = new_link.to_owned();
+                // Validators and hints are of the old URL
+                feed.etag = None;
+                feed.last_modified = None;
+                feed.freshness = None;
+                feed.retry_after = None;
+                feed.self_link = None;
+                feed.self_link_checked = None;
+                self.feeds.insert(new_id, feed.clone());
+                feed
+            }
+        };
+        self.save().unwrap_or_default();
+        Some(result)
+    }
+
     pub fn delete_subscriber(&mut self, subscriber: SubscriberId) -> bool {
         self.subscribed_feeds(subscriber)
             .map(|feeds| {
@@ -227,14 +391,108 @@ impl Database {
             .is_some()
     }
 
+    /// Not saved immediately, losing them only costs a full fetch.
+    /// Return `false` if feed not found
+    pub fn set_validators(&mut self, feed_id: FeedId, validators: Validators) -> bool {
+        self.feeds
+            .get_mut(&feed_id)
+            .map(|feed| {
+                feed.etag = validators.etag;
+                feed.last_modified = validators.last_modified;
+            })
+            .is_some()
+    }
+
+    /// Return `false` if feed not found
+    pub fn set_schedule_hints(
+        &mut self,
+        feed_id: FeedId,
+        freshness: Option<Freshness>,
+        retry_after: Option<SystemTime>,
+    ) -> bool {
+        self.feeds
+            .get_mut(&feed_id)
+            .map(|feed| {
+                feed.freshness = freshness;
+                feed.retry_after = retry_after;
+            })
+            .is_some()
+    }
+
+    /// Return `false` if feed not found
+    pub fn set_backoff(&mut self, feed_id: FeedId, backoff: bool) -> bool {
+        self.feeds
+            .get_mut(&feed_id)
+            .map(|feed| feed.backoff = backoff)
+            .is_some()
+    }
+
+    /// Return `false` if feed not found
+    pub fn set_self_link(&mut self, feed_id: FeedId, self_link: Option<String>) -> bool {
+        let found = self
+            .feeds
+            .get_mut(&feed_id)
+            .map(|feed| feed.self_link = self_link)
+            .is_some();
+        if found {
+            self.save().unwrap_or_default();
+        }
+        found
+    }
+
+    /// Return `false` if feed not found
+    pub fn set_self_link_checked(&mut self, feed_id: FeedId, checked: Option<SystemTime>) -> bool {
+        let found = self
+            .feeds
+            .get_mut(&feed_id)
+            .map(|feed| feed.self_link_checked = checked)
+            .is_some();
+        if found {
+            self.save().unwrap_or_default();
+        }
+        found
+    }
+
+    /// Return `false` if feed not found
+    pub fn set_hub(&mut self, feed_id: FeedId, hub: Option<Hub>) -> bool {
+        let found = self
+            .feeds
+            .get_mut(&feed_id)
+            .map(|feed| {
+                feed.hub = hub;
+            })
+            .is_some();
+        if found {
+            self.save().unwrap_or_default();
+        }
+        found
+    }
+
+    pub fn find_by_hub_callback(&self, callback: &str) -> Option<Feed> {
+        self.feeds
+            .values()
+            .find(|feed| matches!(&feed.hub, Some(hub) if hub.callback == callback))
+            .cloned()
+    }
+
+    /// Whether any of `items` was seen before, feeds without history
+    /// or not found count as seen, there is no gap to fill
+    pub fn has_seen_any(&self, feed_id: FeedId, items: &[feed::Item]) -> bool {
+        match self.feeds.get(&feed_id) {
+            Some(feed) if !feed.hash_list.is_empty() => items
+                .iter()
+                .any(|item| feed.hash_list.contains(&gen_item_hash(item))),
+            _ => true,
+        }
+    }
+
     /// Update the feed in database, return updates
-    pub fn update(&mut self, rss_link: &str, new_feed: feed::Rss) -> Vec<FeedUpdate> {
-        let feed_id = gen_hash(&rss_link);
+    pub fn update(&mut self, feed_id: FeedId, new_feed: feed::Rss) -> Vec<FeedUpdate> {
         if !self.feeds.contains_key(&feed_id) {
             return Vec::new();
         }
 
-        self.reset_down_time(rss_link);
+        self.reset_down_time(feed_id);
         let feed = self.feeds.get_mut(&feed_id).unwrap();
 
         let mut updates = Vec::new();
@@ -251,7 +509,11 @@ impl Database {
         if !new_items.is_empty() {
             updates.push(FeedUpdate::Items(new_items));
 
-            let max_size = items_len * 2;
+            // WebSub pushes may only contain the new items, keep the history
+            let max_size = std::cmp::max(
+                items_len * 2,
+                std::cmp::min(feed.hash_list.len(), MAX_HASH_HISTORY),
+            );
             let mut append: Vec<u64> = feed
                 .hash_list
                 .iter()
@@ -266,6 +528,12 @@ impl Database {
             feed.title = new_feed.title;
         }
         feed.ttl = new_feed.ttl;
+        feed.description = new_feed.description;
+        feed.icon = new_feed.icon;
+        feed.language = new_feed.language;
+        feed.generator = new_feed.generator;
+        feed.recovered = new_feed.recovered;
+        feed.warnings = new_feed.warnings;
         if !updates.is_empty() {
             self.save().unwrap_or_default();
         }
@@ -338,6 +606,143 @@ impl Hasher for Size64Hasher {
 mod test {
     use super::*;
 
+    fn rss(items: &[&str]) -> feed::Rss {
+        feed::Rss {
+            title: "t".into(),
+            items: items
+                .iter()
+                .map(|link| feed::Item {
+                    link: Some(link.to_string()),
+                    ..Default::default()
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn id(link: &str) -> FeedId {
+        feed_id(link, &RequestOptions::default())
+    }
+
+    #[test]
+    fn subscribe_with_request() {
+        let path =
+            std::env::temp_dir().join(format!("rssbot-test-request-{}.json", std::process::id()));
+        let mut db = Database::create(path.clone()).unwrap();
+        let request = RequestOptions::parse(&["bearer:token"]).unwrap();
+        let other = RequestOptions::parse(&["bearer:other"]).unwrap();
+        let link = "http://a/feed";
+        assert!(db.subscribe(1, link, &rss(&[]), &request));
+        assert!(!db.subscribe(1, link, &rss(&[]), &request));
+        assert!(!db.subscribe(1, link, &rss(&[]), &other));
+        // Neither share the token nor replace it
+        assert!(db.subscribe(2, link, &rss(&[]), &RequestOptions::default()));
+        assert!(db.subscribe(3, link, &rss(&[]), &other));
+        assert!(db.subscribe(4, link, &rss(&[]), &request));
+        assert_eq!(db.all_feeds().len(), 3);
+        let feed = db.get_feed(feed_id(link, &request)).unwrap();
+        assert_eq!(feed.request, request);
+        assert_eq!(feed.subscribers, [1, 4].into_iter().collect());
+        assert!(db.get_feed(id(link)).unwrap().request.is_empty());
+
+        let feed = db.unsubscribe(3, link).unwrap();
+        assert_eq!(feed.request, other);
+        assert!(!db.is_subscribed(3, link));
+        assert!(db.is_subscribed(2, link));
+        assert_eq!(db.all_feeds().len(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn migrate_feeds_with_request() {
+        let path =
+            std::env::temp_dir().join(format!("rssbot-test-migrate-{}.json", std::process::id()));
+        let mut db = Database::create(path.clone()).unwrap();
+        let request = RequestOptions::parse(&["bearer:token"]).unwrap();
+        db.subscribe(1, "http://a/1", &rss(&[]), &request);
+        db.subscribe(2, "http://a/2", &rss(&[]), &request);
+        db.subscribe(3, "http://a/3", &rss(&[]), &RequestOptions::default());
+
+        let feed = db
+            .migrate_feed(feed_id("http://a/1", &request), "http://a/moved")
+            .unwrap();
+        assert_eq!(feed.request, request);
+        // A permanent redirect to another site
+        let feed = db.migrate_feed(feed.id(), "http://b/1").unwrap();
+        assert!(feed.request.is_empty());
+        assert!(db.get_feed(id("http://b/1")).unwrap().request.is_empty());
+        assert!(db.is_subscribed(1, "http://b/1"));
+        // Neither share the token nor drop it
+        let feed = db
+            .migrate_feed(feed_id("http://a/2", &request), "http://a/3")
+            .unwrap();
+        assert_eq!(feed.request, request);
+        assert_eq!(feed.subscribers, [2].into_iter().collect());
+        assert_eq!(db.get_feed(id("http://a/3")).unwrap().subscribers.len(), 1);
+        assert!(db.is_subscribed(2, "http://a/3"));
+        assert!(db.is_subscribed(3, "http://a/3"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn migrate_and_delete_feeds() {
+        let path = std::env::temp_dir().join(format!("rssbot-test-{}.json", std::process::id()));
+        let mut db = Database::create(path.clone()).unwrap();
+        let request = RequestOptions::default();
+        db.subscribe(1, "http://a/old", &rss(&["1", "2"]), &request);
+        db.subscribe(2, "http://a/old", &rss(&["1", "2"]), &request);
+        db.subscribe(2, "http://a/new", &rss(&["2", "3"]), &request);
+        db.subscribe(3, "http://a/other", &rss(&[]), &request);
+
+        let feed = db.migrate_feed(id("http://a/old"), "http://a/new").unwrap();
+        assert_eq!(feed.link, "http://a/new");
+        assert_eq!(feed.subscribers.len(), 2);
+        assert!(db
+            .migrate_feed(id("http://a/old"), "http://a/new")
+            .is_none());
+        assert!(!db.is_subscribed(1, "http://a/old"));
+        assert!(db.is_subscribed(1, "http://a/new"));
+        assert_eq!(db.subscribed_feeds(2).unwrap().len(), 1);
+        assert!(db
+            .update(id("http://a/new"), rss(&["1", "2", "3"]))
+            .is_empty());
+
+        let feed = db
+            .migrate_feed(id("http://a/other"), "http://b/other")
+            .unwrap();
+        assert_eq!(feed.link, "http://b/other");
+        assert!(db.is_subscribed(3, "http://b/other"));
+
+        let feed = db.delete_feed(id("http://a/new")).unwrap();
+        assert_eq!(feed.subscribers.len(), 2);
+        assert!(db.subscribed_feeds(1).is_none());
+        assert!(db.subscribed_feeds(2).is_none());
+        assert!(db.delete_feed(id("http://a/new")).is_none());
+        assert_eq!(db.all_feeds().len(), 1);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn hash_list_size() {
+        let path =
+            std::env::temp_dir().join(format!("rssbot-test-hashes-{}.json", std::process::id()));
+        let mut db = Database::create(path.clone()).unwrap();
+        let link = "http://a/feed";
+        db.subscribe(1, link, &rss(&[]), &RequestOptions::default());
+        let hash_list_len = |db: &Database| db.feeds[&id(link)].hash_list.len();
+        // A big catch-up
+        let links: Vec<_> = (0..2000).map(|i| i.to_string()).collect();
+        let links: Vec<_> = links.iter().map(String::as_str).collect();
+        db.update(id(link), rss(&links));
+        assert_eq!(hash_list_len(&db), 2000);
+        // Pushes with only the new items keep some history
+        db.update(id(link), rss(&["a"]));
+        assert_eq!(hash_list_len(&db), MAX_HASH_HISTORY);
+        db.update(id(link), rss(&["b"]));
+        assert_eq!(hash_list_len(&db), MAX_HASH_HISTORY);
+        std::fs::remove_file(path).unwrap();
+    }
+
     #[test]
     fn size64hasher() {
         let mut h = Size64Hasher::default();
@@ -5,27 +5,108 @@ use std::ops::{Deref, DerefMut};
 use std::rc::Rc;
 use std::str;
 
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
 use lazy_static::lazy_static;
 use quick_xml::events::attributes::Attributes;
 use quick_xml::events::BytesStart;
 use quick_xml::events::Event as XmlEvent;
-use quick_xml::Reader as XmlReader;
+use quick_xml::name::{Namespace, ResolveResult};
+use quick_xml::NsReader as XmlReader;
 use regex::Regex;
-use serde::Deserialize;
+use url::Url;
+
+mod charset;
+pub mod json;
+mod recovery;
 
 trait FromXml: Sized {
     fn from_xml<B: std::io::BufRead>(
-        bufs: &BufPool,
+        ctx: &ParseContext,
         reader: &mut XmlReader<B>,
         start: &BytesStart,
     ) -> quick_xml::Result<Self>;
 }
 
+/// Namespaces of the elements we care about
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Ns {
+    /// Elements of the feed itself: RSS 0.9x and 2.0 (no namespace), RSS 0.9,
+    /// RSS 1.0, Atom 0.3 and Atom 1.0. They don't clash with each other, so the
+    /// same element name means the same thing in all of them.
+    Feed,
+    /// http://www.w3.org/1999/02/22-rdf-syntax-ns#
+    Rdf,
+    /// http://purl.org/dc/elements/1.1/
+    Dc,
+    /// http://purl.org/rss/1.0/modules/content/
+    Content,
+    /// http://search.yahoo.com/mrss/
+    Media,
+    /// http://purl.org/rss/1.0/modules/syndication/
+    Sy,
+    /// http://www.itunes.com/dtds/podcast-1.0.dtd
+    Itunes,
+    Other,
+}
+
+impl Ns {
+    fn from_uri(uri: &[u8]) -> Self {
+        match uri {
+            b"http://www.w3.org/2005/Atom"
+            | b"http://purl.org/atom/ns#"
+            | b"http://purl.org/rss/1.0/"
+            | b"http://my.netscape.com/rdf/simple/0.9/" => Ns::Feed,
+            b"http://www.w3.org/1999/02/22-rdf-syntax-ns#" => Ns::Rdf,
+            b"http://purl.org/dc/elements/1.1/" => Ns::Dc,
+            b"http://purl.org/rss/1.0/modules/content/" => Ns::Content,
+            b"http://search.yahoo.com/mrss/" | b"http://search.yahoo.com/mrss" => Ns::Media,
+            b"http://purl.org/rss/1.0/modules/syndication/" => Ns::Sy,
+            b"http://www.itunes.com/dtds/podcast-1.0.dtd" => Ns::Itunes,
+            _ => Ns::Other,
+        }
+    }
+
+    /// Some feeds use well-known prefixes without declaring them
+    fn from_undeclared_prefix(prefix: &[u8]) -> Self {
+        match prefix {
+            b"atom" => Ns::Feed,
+            b"rdf" => Ns::Rdf,
+            b"dc" => Ns::Dc,
+            b"content" => Ns::Content,
+            b"media" => Ns::Media,
+            b"sy" => Ns::Sy,
+            b"itunes" => Ns::Itunes,
+            _ => Ns::Other,
+        }
+    }
+}
+
+/// Resolve the namespace and the local name of an element
+fn resolve_element<'e, B>(
+    ctx: &ParseContext,
+    reader: &XmlReader<B>,
+    e: &'e BytesStart,
+) -> quick_xml::Result<(Ns, Cow<'e, str>)> {
+    let (ns, local_name) = reader.resolve_element(e.name());
+    let ns = match ns {
+        ResolveResult::Unbound => Ns::Feed,
+        ResolveResult::Bound(Namespace(uri)) if ctx.feed_ns.borrow().as_deref() == Some(uri) => {
+            Ns::Feed
+        }
+        ResolveResult::Bound(Namespace(uri)) => Ns::from_uri(uri),
+        ResolveResult::Unknown(prefix) => Ns::from_undeclared_prefix(&prefix),
+    };
+    Ok((ns, reader.decoder().decode(local_name.into_inner())?))
+}
+
 #[derive(Debug, Eq, PartialEq)]
 enum AtomLink<'a> {
     Alternate(String),
     Source(String),
     Hub(String),
+    Next(String),
+    PrevArchive(String),
+    Enclosure(Attachment),
     Other(String, Cow<'a, str>),
 }
 
@@ -35,6 +116,9 @@ fn parse_atom_link<'a, B: std::io::BufRead>(
 ) -> quick_xml::Result<Option<AtomLink<'a>>> {
     let mut href = None;
     let mut rel = None;
+    let mut mime_type = None;
+    let mut length = None;
+    let mut base = None;
     for attribute in attributes {
         let attribute = attribute?;
         let decoder = reader.decoder();
@@ -49,15 +133,36 @@ fn parse_atom_link<'a, B: std::io::BufRead>(
                     unreachable!()
                 })?)
             }
+            "type" => mime_type = Some(attribute.decode_and_unescape_value(reader)?.to_string()),
+            "length" => {
+                length = attribute
+                    .decode_and_unescape_value(reader)?
+                    .trim()
+                    .parse()
+                    .ok()
+            }
+            "xml:base" => base = Some(attribute.decode_and_unescape_value(reader)?.into_owned()),
             _ => (),
         }
     }
     Ok(href.map(move |href| {
+        let href = match base {
+            Some(base) => join_url(&base, &href),
+            None => href,
+        };
         if let Some(rel) = rel {
             match rel.as_ref() {
                 "alternate" => AtomLink::Alternate(href),
                 "self" => AtomLink::Source(href),
                 "hub" => AtomLink::Hub(href),
+                "next" => AtomLink::Next(href),
+                "prev-archive" => AtomLink::PrevArchive(href),
+                "enclosure" => AtomLink::Enclosure(Attachment {
+                    url: href,
+                    mime_type,
+                    length,
+                    duration: None,
+                }),
                 _ => AtomLink::Other(href, rel),
             }
         } else {
@@ -70,11 +175,11 @@ struct SkipThisElement;
 
 impl FromXml for SkipThisElement {
     fn from_xml<B: std::io::BufRead>(
-        bufs: &BufPool,
+        ctx: &ParseContext,
         reader: &mut XmlReader<B>,
         _start: &BytesStart,
     ) -> quick_xml::Result<Self> {
-        let mut buf = bufs.pop();
+        let mut buf = ctx.bufs.pop();
         let mut depth = 1u64;
         loop {
             match reader.read_event_into(&mut buf) {
@@ -93,16 +198,16 @@ impl FromXml for SkipThisElement {
 
 impl FromXml for Option<u32> {
     fn from_xml<B: std::io::BufRead>(
-        bufs: &BufPool,
+        ctx: &ParseContext,
         reader: &mut XmlReader<B>,
         _start: &BytesStart,
     ) -> quick_xml::Result<Self> {
-        let mut buf = bufs.pop();
+        let mut buf = ctx.bufs.pop();
         let mut output = None;
         loop {
             match reader.read_event_into(&mut buf) {
                 Ok(XmlEvent::Start(ref e)) => {
-                    SkipThisElement::from_xml(bufs, reader, e)?;
+                    SkipThisElement::from_xml(ctx, reader, e)?;
                 }
                 Ok(XmlEvent::Text(ref e)) => {
                     let text = reader.decoder().decode(e)?;
@@ -120,16 +225,16 @@ impl FromXml for Option<u32> {
 
 impl FromXml for Option<String> {
     fn from_xml<B: std::io::BufRead>(
-        bufs: &BufPool,
+        ctx: &ParseContext,
         reader: &mut XmlReader<B>,
         _start: &BytesStart,
     ) -> quick_xml::Result<Self> {
-        let mut buf = bufs.pop();
+        let mut buf = ctx.bufs.pop();
         let mut content: Option<String> = None;
         loop {
             match reader.read_event_into(&mut buf) {
                 Ok(XmlEvent::Start(ref e)) => {
-                    SkipThisElement::from_xml(bufs, reader, e)?;
+                    SkipThisElement::from_xml(ctx, reader, e)?;
                 }
                 Ok(XmlEvent::Text(ref e)) => {
                     let raw = reader.decoder().decode(e.as_ref())?;
@@ -150,26 +255,82 @@ impl FromXml for Option<String> {
     }
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Rss {
     pub title: String,
-    #[serde(rename = "home_page_url", default)]
     pub link: String,
-    #[serde(rename = "feed_url")]
     pub source: Option<String>,
+    /// WebSub hub
+    pub hub: Option<String>,
+    /// The next page of a paged feed, usually older items (RFC 5005)
+    pub next_page: Option<String>,
+    /// The previous archive document of an archived feed (RFC 5005)
+    pub prev_archive: Option<String>,
     pub ttl: Option<u32>,
+    /// RSS `description`, Atom `subtitle`
+    pub description: Option<String>,
+    /// RSS `image`, Atom `icon` or `logo`
+    pub icon: Option<String>,
+    /// RSS `language`, Atom `xml:lang`
+    pub language: Option<String>,
+    pub generator: Option<String>,
+    /// The feed is malformed and was parsed by [`parse_recovering`]
+    pub recovered: bool,
+    pub format: Format,
+    /// Non-fatal problems found while parsing
+    pub warnings: Vec<Warning>,
     pub items: Vec<Item>,
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Unknown,
+    /// RSS 0.9, 0.91, 0.92, 0.93 and 0.94
+    Rss09x,
+    Rss10,
+    Rss20,
+    Atom03,
+    Atom10,
+    JsonFeed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    MissingTitle,
+    /// Index of the item which has neither link nor id,
+    /// it can't be told apart from others after being edited
+    UnidentifiableItem(usize),
+    InvalidTtl(String),
+    InvalidDate(String),
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Warning::MissingTitle => write!(f, "feed has no title"),
+            Warning::UnidentifiableItem(i) => write!(f, "item {} has neither link nor id", i),
+            Warning::InvalidTtl(s) => write!(f, "invalid ttl: {:?}", s),
+            Warning::InvalidDate(s) => write!(f, "invalid date: {:?}", s),
+        }
+    }
+}
+
 impl FromXml for Rss {
     fn from_xml<B: std::io::BufRead>(
-        bufs: &BufPool,
+        ctx: &ParseContext,
         reader: &mut XmlReader<B>,
-        _start: &BytesStart,
+        start: &BytesStart,
     ) -> quick_xml::Result<Self> {
-        let mut buf = bufs.pop();
+        let mut buf = ctx.bufs.pop();
         let mut rss = Rss::default();
         let mut reading_rss_1_0_head = false;
+        let mut logo = None;
+        let base = xml_base(reader, start)?;
+
+        if let Some(lang) = start.try_get_attribute("xml:lang")? {
+            rss.language = Some(lang.decode_and_unescape_value(reader)?.into_owned());
+        }
 
         // http://purl.org/rss/1.0/modules/syndication/
         let mut sy_period: Option<SyPeriod> = None;
@@ -178,30 +339,42 @@ impl FromXml for Rss {
         loop {
             match reader.read_event_into(&mut buf) {
                 Ok(XmlEvent::Empty(ref e)) => {
-                    if reader.decoder().decode(e.local_name().as_ref())?.as_ref() == "link" {
-                        match parse_atom_link(reader, e.attributes())? {
+                    let (ns, name) = resolve_element(ctx, reader, e)?;
+                    match (ns, &*name) {
+                        (Ns::Feed, "link") => match parse_atom_link(reader, e.attributes())? {
                             Some(AtomLink::Alternate(link)) => rss.link = link,
                             Some(AtomLink::Source(link)) => rss.source = Some(link),
+                            Some(AtomLink::Hub(link)) => rss.hub = Some(link),
+                            Some(AtomLink::Next(link)) => rss.next_page = Some(link),
+                            Some(AtomLink::PrevArchive(link)) => rss.prev_archive = Some(link),
                             _ => {}
+                        },
+                        (Ns::Itunes, "image") if rss.icon.is_none() => {
+                            if let Some(href) = e.try_get_attribute("href")? {
+                                rss.icon =
+                                    Some(href.decode_and_unescape_value(reader)?.into_owned());
+                            }
                         }
+                        _ => {}
                     }
                 }
                 Ok(XmlEvent::Start(ref e)) => {
-                    match &*reader.decoder().decode(e.local_name().as_ref())? {
-                        "channel" => {
+                    let (ns, name) = resolve_element(ctx, reader, e)?;
+                    match (ns, &*name) {
+                        (Ns::Feed, "channel") => {
                             // RSS 0.9 1.0
                             reading_rss_1_0_head = true;
                         }
-                        "title" => {
+                        (Ns::Feed, "title") => {
                             if let Some(title) =
-                                <Option<String> as FromXml>::from_xml(bufs, reader, e)?
+                                <Option<String> as FromXml>::from_xml(ctx, reader, e)?
                             {
                                 rss.title = title;
                             }
                         }
-                        "link" => {
+                        (Ns::Feed, "link") => {
                             if let Some(link) =
-                                <Option<String> as FromXml>::from_xml(bufs, reader, e)?
+                                <Option<String> as FromXml>::from_xml(ctx, reader, e)?
                             {
                                 // RSS
                                 rss.link = link;
@@ -210,24 +383,58 @@ impl FromXml for Rss {
                                 match parse_atom_link(reader, e.attributes())? {
                                     Some(AtomLink::Alternate(link)) => rss.link = link,
                                     Some(AtomLink::Source(link)) => rss.source = Some(link),
+                                    Some(AtomLink::Hub(link)) => rss.hub = Some(link),
+                                    Some(AtomLink::Next(link)) => rss.next_page = Some(link),
+                                    Some(AtomLink::PrevArchive(link)) => {
+                                        rss.prev_archive = Some(link)
+                                    }
                                     _ => {}
                                 }
                             }
                         }
-                        "item" | "entry" => {
-                            rss.items.push(Item::from_xml(bufs, reader, e)?);
+                        (Ns::Feed, "item" | "entry") => {
+                            rss.items.push(Item::from_xml(ctx, reader, e)?);
+                        }
+                        // Atom 0.3 uses `tagline`
+                        (Ns::Feed, "description" | "subtitle" | "tagline") => {
+                            rss.description =
+                                <Option<String> as FromXml>::from_xml(ctx, reader, e)?;
+                        }
+                        (Ns::Feed, "image") => {
+                            if let Some(url) = parse_rss_image(ctx, reader)? {
+                                rss.icon = Some(url);
+                            }
                         }
-                        "ttl" => {
-                            rss.ttl = <Option<u32> as FromXml>::from_xml(bufs, reader, e)?;
+                        (Ns::Feed, "icon") => {
+                            rss.icon = <Option<String> as FromXml>::from_xml(ctx, reader, e)?;
                         }
-                        "updatePeriod" => {
-                            sy_period = <Option<SyPeriod> as FromXml>::from_xml(bufs, reader, e)?;
+                        (Ns::Feed, "logo") => {
+                            logo = <Option<String> as FromXml>::from_xml(ctx, reader, e)?;
                         }
-                        "updateFrequency" => {
-                            sy_freq = <Option<u32> as FromXml>::from_xml(bufs, reader, e)?;
+                        (Ns::Feed | Ns::Dc, "language") => {
+                            rss.language = <Option<String> as FromXml>::from_xml(ctx, reader, e)?;
+                        }
+                        (Ns::Feed, "generator") => {
+                            rss.generator = <Option<String> as FromXml>::from_xml(ctx, reader, e)?;
+                        }
+                        (Ns::Feed, "ttl") => {
+                            let text = <Option<String> as FromXml>::from_xml(ctx, reader, e)?;
+                            rss.ttl = text.and_then(|text| match text.trim().parse() {
+                                Ok(ttl) => Some(ttl),
+                                Err(_) => {
+                                    ctx.warn(Warning::InvalidTtl(text));
+                                    None
+                                }
+                            });
+                        }
+                        (Ns::Sy, "updatePeriod") => {
+                            sy_period = <Option<SyPeriod> as FromXml>::from_xml(ctx, reader, e)?;
+                        }
+                        (Ns::Sy, "updateFrequency") => {
+                            sy_freq = <Option<u32> as FromXml>::from_xml(ctx, reader, e)?;
                         }
                         _ => {
-                            SkipThisElement::from_xml(bufs, reader, e)?;
+                            SkipThisElement::from_xml(ctx, reader, e)?;
                         }
                     }
                 }
@@ -241,6 +448,10 @@ impl FromXml for Rss {
             }
             buf.clear();
         }
+        // Atom `icon` is preferred, it's square
+        if rss.icon.is_none() {
+            rss.icon = logo;
+        }
         if rss.ttl.is_none() {
             let freq = sy_freq.unwrap_or(1); // 1 is the default value
             rss.ttl = match sy_period {
@@ -252,60 +463,306 @@ impl FromXml for Rss {
                 None => None,
             };
         }
+        if let Some(base) = base {
+            rss.map_urls(|url| join_url(&base, url));
+        }
         Ok(rss)
     }
 }
 
-#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+impl Rss {
+    /// Find problems of the whole feed
+    fn check(&mut self) {
+        if self.title.trim().is_empty() {
+            self.warnings.push(Warning::MissingTitle);
+        }
+        for (i, item) in self.items.iter().enumerate() {
+            if item.link.is_none() && item.id.is_none() {
+                self.warnings.push(Warning::UnidentifiableItem(i));
+            }
+        }
+    }
+
+    fn map_urls(&mut self, f: impl Fn(&str) -> String) {
+        if !self.link.is_empty() {
+            self.link = f(&self.link);
+        }
+        for url in [&mut self.icon, &mut self.next_page, &mut self.prev_archive]
+            .into_iter()
+            .flatten()
+        {
+            *url = f(url);
+        }
+        for item in &mut self.items {
+            item.map_urls(&f);
+        }
+    }
+}
+
+/// Return the `url` of RSS `image`
+fn parse_rss_image<B: std::io::BufRead>(
+    ctx: &ParseContext,
+    reader: &mut XmlReader<B>,
+) -> quick_xml::Result<Option<String>> {
+    let mut buf = ctx.bufs.pop();
+    let mut url = None;
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(XmlEvent::Start(ref e)) => {
+                let (ns, name) = resolve_element(ctx, reader, e)?;
+                if let (Ns::Feed, "url") = (ns, &*name) {
+                    url = <Option<String> as FromXml>::from_xml(ctx, reader, e)?;
+                } else {
+                    SkipThisElement::from_xml(ctx, reader, e)?;
+                }
+            }
+            Ok(XmlEvent::End(_)) | Ok(XmlEvent::Eof) => break,
+            Err(err) => return Err(err),
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok(url)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct Item {
     pub title: Option<String>,
-    #[serde(rename = "url")]
     pub link: Option<String>,
     pub id: Option<String>,
+    /// RSS `description`, Atom `summary`
+    pub summary: Option<Text>,
+    /// RSS `content:encoded`, Atom `content`
+    pub content: Option<Text>,
+    /// RSS `pubDate`, Atom `published`, `dc:date`
+    pub published: Option<DateTime<FixedOffset>>,
+    /// Atom `updated`
+    pub updated: Option<DateTime<FixedOffset>>,
+    /// RSS `enclosure`, Media RSS, Atom `link rel="enclosure"`
+    pub attachments: Vec<Attachment>,
+    /// RSS `author`, `dc:creator`, Atom `author`
+    pub authors: Vec<Person>,
+    /// Atom `contributor`
+    pub contributors: Vec<Person>,
+    pub categories: Vec<Category>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Person {
+    pub name: String,
+    pub email: Option<String>,
+    pub uri: Option<String>,
+}
+
+impl Person {
+    /// Parse the RSS style `email (Name)`
+    fn from_rss_author(s: &str) -> Self {
+        lazy_static! {
+            static ref EMAIL_NAME: Regex = Regex::new(r"^(\S+@\S+)\s*\((.+)\)$").unwrap();
+        }
+        let s = s.trim();
+        if let Some(captures) = EMAIL_NAME.captures(s) {
+            Person {
+                name: captures[2].trim().to_owned(),
+                email: Some(captures[1].to_owned()),
+                uri: None,
+            }
+        } else {
+            Person {
+                name: s.to_owned(),
+                email: s.contains('@').then(|| s.to_owned()),
+                uri: None,
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Category {
+    /// RSS category text, or Atom `term`
+    pub term: String,
+    /// Atom `label`, a human-readable version of `term`
+    pub label: Option<String>,
+    /// RSS `domain`, Atom `scheme`
+    pub scheme: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attachment {
+    pub url: String,
+    pub mime_type: Option<String>,
+    /// In bytes
+    pub length: Option<u64>,
+    /// In seconds
+    pub duration: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Text {
+    pub kind: TextKind,
+    pub value: String,
+}
+
+impl Text {
+    fn new(kind: TextKind, value: String) -> Self {
+        Text { kind, value }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextKind {
+    Text,
+    Html,
+    Xhtml,
+    /// Unknown Atom `type` attribute, usually a MIME type
+    Other(String),
+}
+
+impl TextKind {
+    fn from_type_attr(s: &str) -> Self {
+        match s {
+            "text" | "text/plain" => TextKind::Text,
+            "html" | "text/html" => TextKind::Html,
+            "xhtml" | "application/xhtml+xml" => TextKind::Xhtml,
+            _ => TextKind::Other(s.to_owned()),
+        }
+    }
 }
 
 impl FromXml for Item {
     fn from_xml<B: std::io::BufRead>(
-        bufs: &BufPool,
+        ctx: &ParseContext,
         reader: &mut XmlReader<B>,
-        _start: &BytesStart,
+        start: &BytesStart,
     ) -> quick_xml::Result<Self> {
-        let mut buf = bufs.pop();
+        let mut buf = ctx.bufs.pop();
         let mut item = Item::default();
+        let base = xml_base(reader, start)?;
+        let mut itunes_duration = None;
         loop {
             match reader.read_event_into(&mut buf) {
                 Ok(XmlEvent::Empty(ref e)) => {
-                    if reader.decoder().decode(e.name().as_ref())? == "link" {
-                        if let Some(AtomLink::Alternate(link)) =
-                            parse_atom_link(reader, e.attributes())?
-                        {
-                            item.link = Some(link);
+                    let (ns, name) = resolve_element(ctx, reader, e)?;
+                    match (ns, &*name) {
+                        (Ns::Feed, "link") => match parse_atom_link(reader, e.attributes())? {
+                            Some(AtomLink::Alternate(link)) => item.link = Some(link),
+                            Some(AtomLink::Enclosure(attachment)) => {
+                                item.attachments.push(attachment)
+                            }
+                            _ => {}
+                        },
+                        (Ns::Feed, "enclosure") | (Ns::Media, "content" | "thumbnail") => {
+                            item.attachments.extend(parse_attachment(reader, e)?);
+                        }
+                        (Ns::Feed, "category") => {
+                            item.categories.extend(parse_category(reader, e, None)?);
                         }
+                        _ => {}
                     }
                 }
                 Ok(XmlEvent::Start(ref e)) => {
-                    match &*reader.decoder().decode(e.name().as_ref())? {
-                        "title" => {
-                            item.title = <Option<String> as FromXml>::from_xml(bufs, reader, e)?;
+                    let (ns, name) = resolve_element(ctx, reader, e)?;
+                    match (ns, &*name) {
+                        (Ns::Feed, "title") => {
+                            item.title = <Option<String> as FromXml>::from_xml(ctx, reader, e)?;
                         }
-                        "link" => {
+                        (Ns::Feed, "link") => {
                             if let Some(link) =
-                                <Option<String> as FromXml>::from_xml(bufs, reader, e)?
+                                <Option<String> as FromXml>::from_xml(ctx, reader, e)?
                             {
                                 // RSS
                                 item.link = Some(link);
-                            } else if let Some(AtomLink::Alternate(link)) =
-                                parse_atom_link(reader, e.attributes())?
-                            {
+                            } else {
                                 // ATOM
-                                item.link = Some(link);
+                                match parse_atom_link(reader, e.attributes())? {
+                                    Some(AtomLink::Alternate(link)) => item.link = Some(link),
+                                    Some(AtomLink::Enclosure(attachment)) => {
+                                        item.attachments.push(attachment)
+                                    }
+                                    _ => {}
+                                }
+                            }
+                        }
+                        (Ns::Feed, "enclosure") | (Ns::Media, "content" | "thumbnail") => {
+                            item.attachments.extend(parse_attachment(reader, e)?);
+                            SkipThisElement::from_xml(ctx, reader, e)?;
+                        }
+                        (Ns::Media, "group") => {
+                            item.attachments.extend(parse_media_group(ctx, reader)?);
+                        }
+                        (Ns::Feed, "author") => {
+                            let author = <Option<Person> as FromXml>::from_xml(ctx, reader, e)?;
+                            item.authors.extend(author);
+                        }
+                        (Ns::Feed, "contributor") => {
+                            let contributor =
+                                <Option<Person> as FromXml>::from_xml(ctx, reader, e)?;
+                            item.contributors.extend(contributor);
+                        }
+                        (Ns::Dc, "creator") => {
+                            if let Some(name) =
+                                <Option<String> as FromXml>::from_xml(ctx, reader, e)?
+                            {
+                                item.authors.push(Person {
+                                    name,
+                                    ..Person::default()
+                                });
                             }
                         }
-                        "id" | "guid" => {
-                            item.id = <Option<String> as FromXml>::from_xml(bufs, reader, e)?;
+                        (Ns::Feed, "category") => {
+                            let text = <Option<String> as FromXml>::from_xml(ctx, reader, e)?;
+                            item.categories.extend(parse_category(reader, e, text)?);
+                        }
+                        (Ns::Itunes, "duration") => {
+                            itunes_duration =
+                                <Option<String> as FromXml>::from_xml(ctx, reader, e)?
+                                    .as_deref()
+                                    .and_then(parse_duration);
+                        }
+                        (Ns::Feed, "id" | "guid") => {
+                            item.id = <Option<String> as FromXml>::from_xml(ctx, reader, e)?;
+                        }
+                        (Ns::Feed, "description") => {
+                            item.summary = <Option<String> as FromXml>::from_xml(ctx, reader, e)?
+                                .map(|html| Text::new(TextKind::Html, html));
+                        }
+                        (Ns::Content, "encoded") => {
+                            item.content = <Option<String> as FromXml>::from_xml(ctx, reader, e)?
+                                .map(|html| Text::new(TextKind::Html, html));
+                        }
+                        (Ns::Feed, "summary") => {
+                            item.summary = parse_atom_text(ctx, reader, e)?;
+                        }
+                        (Ns::Feed, "content") => {
+                            let content = parse_atom_text(ctx, reader, e)?;
+                            // Atom 0.3 allows multiple contents, keep the first one
+                            if item.content.is_none() {
+                                item.content = content;
+                            }
+                        }
+                        (Ns::Feed, "pubDate") => {
+                            let text = <Option<String> as FromXml>::from_xml(ctx, reader, e)?;
+                            item.published = ctx.parse_date(text, parse_rfc822_date);
+                        }
+                        // Atom 0.3 uses `issued` and `modified`
+                        (Ns::Feed, "published" | "issued") => {
+                            let text = <Option<String> as FromXml>::from_xml(ctx, reader, e)?;
+                            item.published = ctx.parse_date(text, parse_w3c_date);
+                        }
+                        (Ns::Feed, "updated" | "modified") => {
+                            let text = <Option<String> as FromXml>::from_xml(ctx, reader, e)?;
+                            item.updated = ctx.parse_date(text, parse_w3c_date);
+                        }
+                        (Ns::Dc, "date") => {
+                            let text = <Option<String> as FromXml>::from_xml(ctx, reader, e)?;
+                            let date = ctx.parse_date(text, parse_w3c_date);
+                            // `pubDate` is more specific
+                            if item.published.is_none() {
+                                item.published = date;
+                            }
                         }
                         _ => {
-                            SkipThisElement::from_xml(bufs, reader, e)?;
+                            SkipThisElement::from_xml(ctx, reader, e)?;
                         }
                     }
                 }
@@ -315,10 +772,371 @@ impl FromXml for Item {
             }
             buf.clear();
         }
+        // `itunes:duration` describes the enclosure
+        if let Some(attachment) = item.attachments.first_mut() {
+            attachment.duration = attachment.duration.or(itunes_duration);
+        }
+        if let Some(base) = base {
+            item.map_urls(|url| join_url(&base, url));
+        }
         Ok(item)
     }
 }
 
+impl Item {
+    fn map_urls(&mut self, f: impl Fn(&str) -> String) {
+        if let Some(link) = &mut self.link {
+            *link = f(link);
+        }
+        for attachment in &mut self.attachments {
+            attachment.url = f(&attachment.url);
+        }
+    }
+}
+
+/// Parse an Atom text construct, `type` defaults to `text`
+fn parse_atom_text<B: std::io::BufRead>(
+    ctx: &ParseContext,
+    reader: &mut XmlReader<B>,
+    start: &BytesStart,
+) -> quick_xml::Result<Option<Text>> {
+    let kind = match start.try_get_attribute("type")? {
+        Some(attr) => TextKind::from_type_attr(&attr.decode_and_unescape_value(reader)?),
+        None => TextKind::Text,
+    };
+    let value = if kind == TextKind::Xhtml {
+        Some(read_inner_xml(ctx, reader)?)
+    } else {
+        <Option<String> as FromXml>::from_xml(ctx, reader, start)?
+    };
+    Ok(value.map(|value| Text::new(kind, value)))
+}
+
+/// Atom `author` and `contributor` are constructs of `name`, `email` and `uri`,
+/// RSS `author` is a text of `email (Name)`
+impl FromXml for Option<Person> {
+    fn from_xml<B: std::io::BufRead>(
+        ctx: &ParseContext,
+        reader: &mut XmlReader<B>,
+        _start: &BytesStart,
+    ) -> quick_xml::Result<Self> {
+        let mut buf = ctx.bufs.pop();
+        let mut person = Person::default();
+        let mut text = None;
+        loop {
+            match reader.read_event_into(&mut buf) {
+                Ok(XmlEvent::Start(ref e)) => {
+                    let (ns, name) = resolve_element(ctx, reader, e)?;
+                    let value = <Option<String> as FromXml>::from_xml(ctx, reader, e)?;
+                    match (ns, &*name) {
+                        (Ns::Feed, "name") => person.name = value.unwrap_or_default(),
+                        (Ns::Feed, "email") => person.email = value,
+                        // Atom 0.3 uses `url`
+                        (Ns::Feed, "uri" | "url") => person.uri = value,
+                        _ => (),
+                    }
+                }
+                Ok(XmlEvent::Text(ref e)) => {
+                    let raw = reader.decoder().decode(e.as_ref())?;
+                    text = Some(quick_xml::escape::unescape(&raw)?.into_owned());
+                }
+                Ok(XmlEvent::CData(ref e)) => {
+                    text = Some(reader.decoder().decode(e)?.into_owned());
+                }
+                Ok(XmlEvent::End(_)) | Ok(XmlEvent::Eof) => break,
+                Err(err) => return Err(err),
+                _ => (),
+            }
+            buf.clear();
+        }
+        if let Some(text) = text {
+            return Ok(Some(Person::from_rss_author(&text)));
+        }
+        Ok(if person == Person::default() {
+            None
+        } else {
+            Some(person)
+        })
+    }
+}
+
+/// Parse RSS `category` with its text, or Atom `category` with attributes
+fn parse_category<B: std::io::BufRead>(
+    reader: &XmlReader<B>,
+    start: &BytesStart,
+    text: Option<String>,
+) -> quick_xml::Result<Option<Category>> {
+    let mut term = None;
+    let mut label = None;
+    let mut scheme = None;
+    for attribute in start.attributes() {
+        let attribute = attribute?;
+        let value = attribute.decode_and_unescape_value(reader)?.into_owned();
+        match attribute.key.as_ref() {
+            b"term" => term = Some(value),
+            b"label" => label = Some(value),
+            b"scheme" | b"domain" => scheme = Some(value),
+            _ => (),
+        }
+    }
+    Ok(term.or(text).map(|term| Category {
+        term,
+        label,
+        scheme,
+    }))
+}
+
+/// Parse RSS `enclosure`, `media:content` and `media:thumbnail`
+fn parse_attachment<B: std::io::BufRead>(
+    reader: &XmlReader<B>,
+    start: &BytesStart,
+) -> quick_xml::Result<Option<Attachment>> {
+    let mut url = None;
+    let mut attachment = Attachment {
+        url: String::new(),
+        mime_type: None,
+        length: None,
+        duration: None,
+    };
+    for attribute in start.attributes() {
+        let attribute = attribute?;
+        let value = attribute.decode_and_unescape_value(reader)?;
+        match attribute.key.as_ref() {
+            b"url" => url = Some(value.into_owned()),
+            b"type" => attachment.mime_type = Some(value.into_owned()),
+            // `fileSize` is used by Media RSS
+            b"length" | b"fileSize" => attachment.length = value.trim().parse().ok(),
+            b"duration" => attachment.duration = parse_duration(&value),
+            _ => (),
+        }
+    }
+    Ok(url.map(|url| Attachment { url, ..attachment }))
+}
+
+fn parse_media_group<B: std::io::BufRead>(
+    ctx: &ParseContext,
+    reader: &mut XmlReader<B>,
+) -> quick_xml::Result<Vec<Attachment>> {
+    let mut buf = ctx.bufs.pop();
+    let mut attachments = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(XmlEvent::Empty(ref e)) => {
+                let (ns, name) = resolve_element(ctx, reader, e)?;
+                if let (Ns::Media, "content" | "thumbnail") = (ns, &*name) {
+                    attachments.extend(parse_attachment(reader, e)?);
+                }
+            }
+            Ok(XmlEvent::Start(ref e)) => {
+                let (ns, name) = resolve_element(ctx, reader, e)?;
+                if let (Ns::Media, "content" | "thumbnail") = (ns, &*name) {
+                    attachments.extend(parse_attachment(reader, e)?);
+                }
+                SkipThisElement::from_xml(ctx, reader, e)?;
+            }
+            Ok(XmlEvent::End(_)) | Ok(XmlEvent::Eof) => break,
+            Err(err) => return Err(err),
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok(attachments)
+}
+
+/// Parse durations in seconds, or `[[HH:]MM:]SS` used by iTunes
+fn parse_duration(s: &str) -> Option<u64> {
+    s.trim().split(':').try_fold(0, |total, part| {
+        // Ignore fractional seconds
+        let n = part.split('.').next()?.parse::<u64>().ok()?;
+        Some(total * 60 + n)
+    })
+}
+
+/// Read the children of current element as raw markup,
+/// the wrapping `<div>` required by Atom xhtml constructs is removed.
+fn read_inner_xml<B: std::io::BufRead>(
+    ctx: &ParseContext,
+    reader: &mut XmlReader<B>,
+) -> quick_xml::Result<String> {
+    let mut buf = ctx.bufs.pop();
+    let mut output = String::new();
+    let mut depth = 0u64;
+    let mut wrapper_depth = None;
+    // Whitespaces are meaningful in markup
+    reader.trim_text(false);
+    loop {
+        match reader.read_event_into(&mut buf) {
+            Ok(XmlEvent::Start(ref e)) => {
+                depth += 1;
+                if depth == 1 && wrapper_depth.is_none() && e.local_name().as_ref() == b"div" {
+                    wrapper_depth = Some(depth);
+                } else {
+                    output.push('<');
+                    output.push_str(&reader.decoder().decode(e)?);
+                    output.push('>');
+                }
+            }
+            Ok(XmlEvent::Empty(ref e)) => {
+                output.push('<');
+                output.push_str(&reader.decoder().decode(e)?);
+                output.push_str("/>");
+            }
+            Ok(XmlEvent::End(_)) if depth == 0 => break,
+            Ok(XmlEvent::End(ref e)) => {
+                if wrapper_depth != Some(depth) {
+                    output.push_str("</");
+                    output.push_str(&reader.decoder().decode(e)?);
+                    output.push('>');
+                }
+                depth -= 1;
+            }
+            Ok(XmlEvent::Text(ref e)) => {
+                // Keep it escaped, the output is markup
+                output.push_str(&reader.decoder().decode(e)?);
+            }
+            Ok(XmlEvent::CData(ref e)) => {
+                let text = reader.decoder().decode(e)?;
+                output.push_str(&quick_xml::escape::escape(&text));
+            }
+            Ok(XmlEvent::Eof) => break,
+            Err(err) => return Err(err),
+            _ => (),
+        }
+        buf.clear();
+    }
+    reader.trim_text(true);
+    Ok(output.trim().to_owned())
+}
+
+/// Parse RFC 822 dates used by RSS, with the tolerance for real world variants:
+/// missing or wrong weekday, full month names, two-digit years, missing seconds,
+/// colons in offset, non-standard or missing timezone names.
+/// Some feeds even put an ISO 8601 date in `pubDate`, it's accepted too.
+fn parse_rfc822_date(s: &str) -> Option<DateTime<FixedOffset>> {
+    let s = s.trim();
+    if let Some(date) = parse_w3c_date(s) {
+        return Some(date);
+    }
+    // Weekday is redundant, and often wrong
+    let s = s.split_once(',').map_or(s, |(_, s)| s);
+    let mut tokens = s.split_whitespace().peekable();
+    if tokens
+        .peek()?
+        .starts_with(|c: char| c.is_ascii_alphabetic())
+    {
+        tokens.next();
+    }
+    let day: u32 = tokens.next()?.parse().ok()?;
+    let month = parse_month_name(tokens.next()?)?;
+    let year = match tokens.next()?.parse::<i32>().ok()? {
+        // https://datatracker.ietf.org/doc/html/rfc2822#section-4.3
+        year @ 0..=49 => year + 2000,
+        year @ 50..=999 => year + 1900,
+        year => year,
+    };
+    let mut time = tokens.next()?.split(':');
+    let hour: u32 = time.next()?.parse().ok()?;
+    let min: u32 = time.next()?.parse().ok()?;
+    let sec: u32 = match time.next() {
+        // Ignore fractional seconds
+        Some(sec) => sec.split('.').next()?.parse().ok()?,
+        None => 0,
+    };
+    let offset = match tokens.next() {
+        Some(zone) => parse_timezone(zone)?,
+        None => 0,
+    };
+
+    let date = NaiveDate::from_ymd_opt(year, month, day)?;
+    let time = NaiveTime::from_hms_opt(hour, min, sec)?;
+    FixedOffset::east_opt(offset)?
+        .from_local_datetime(&date.and_time(time))
+        .single()
+}
+
+fn parse_month_name(s: &str) -> Option<u32> {
+    const MONTHS: [&str; 12] = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ];
+    let prefix = s.get(..3)?.to_ascii_lowercase();
+    MONTHS
+        .iter()
+        .position(|m| *m == prefix)
+        .map(|i| i as u32 + 1)
+}
+
+/// Return the offset in seconds
+fn parse_timezone(s: &str) -> Option<i32> {
+    let sign = match s.as_bytes().first()? {
+        b'+' => 1,
+        b'-' => -1,
+        _ => 0,
+    };
+    if sign != 0 {
+        let digits = s[1..].replace(':', "");
+        let (hour, min) = match digits.len() {
+            1 | 2 => (digits.parse::<i32>().ok()?, 0),
+            4 => (digits[..2].parse().ok()?, digits[2..].parse::<i32>().ok()?),
+            _ => return None,
+        };
+        return Some(sign * (hour * 60 + min) * 60);
+    }
+    let hour = match s.to_ascii_uppercase().as_str() {
+        "EDT" => -4,
+        "EST" | "CDT" => -5,
+        "CST" | "MDT" => -6,
+        "MST" | "PDT" => -7,
+        "PST" => -8,
+        "BST" | "CET" => 1,
+        "CEST" | "EET" => 2,
+        "EEST" | "MSK" => 3,
+        "HKT" | "SGT" | "AWST" => 8,
+        "JST" | "KST" => 9,
+        "AEST" => 10,
+        "AEDT" => 11,
+        // GMT, UT, UTC, Z and the unknown ones
+        // https://datatracker.ietf.org/doc/html/rfc2822#section-4.3
+        _ => 0,
+    };
+    Some(hour * 60 * 60)
+}
+
+/// Parse W3C-DTF (a profile of ISO 8601) dates used by Atom, Dublin Core and JSON Feed.
+/// Dates without timezone are treated as UTC.
+fn parse_w3c_date(s: &str) -> Option<DateTime<FixedOffset>> {
+    const WITH_OFFSET: [&str; 3] = [
+        "%Y-%m-%dT%H:%M:%S%.f%:z",
+        "%Y-%m-%dT%H:%M%:z",
+        "%Y-%m-%dT%H:%M:%S%.f%z",
+    ];
+    const WITHOUT_OFFSET: [&str; 2] = ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%dT%H:%M"];
+
+    let s = s.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+        return Some(date);
+    }
+    // Some generators use a space as separator, or "+0800" as offset
+    let s = s.replacen(' ', "T", 1);
+    let s = s
+        .strip_suffix('Z')
+        .map_or(s.clone(), |s| format!("{}+00:00", s));
+    let utc = FixedOffset::east_opt(0).unwrap();
+    WITH_OFFSET
+        .iter()
+        .find_map(|fmt| DateTime::parse_from_str(&s, fmt).ok())
+        .or_else(|| {
+            WITHOUT_OFFSET
+                .iter()
+                .find_map(|fmt| NaiveDateTime::parse_from_str(&s, fmt).ok())
+                .or_else(|| {
+                    NaiveDate::parse_from_str(&s, "%Y-%m-%d")
+                        .ok()
+                        .map(|date| date.and_time(NaiveTime::MIN))
+                })
+                .and_then(|date| utc.from_local_datetime(&date).single())
+        })
+}
+
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
 enum SyPeriod {
     Hourly,
@@ -330,16 +1148,16 @@ enum SyPeriod {
 
 impl FromXml for Option<SyPeriod> {
     fn from_xml<B: std::io::BufRead>(
-        bufs: &BufPool,
+        ctx: &ParseContext,
         reader: &mut XmlReader<B>,
         _start: &BytesStart,
     ) -> quick_xml::Result<Self> {
-        let mut buf = bufs.pop();
+        let mut buf = ctx.bufs.pop();
         let mut output = None;
         loop {
             match reader.read_event_into(&mut buf) {
                 Ok(XmlEvent::Start(ref e)) => {
-                    SkipThisElement::from_xml(bufs, reader, e)?;
+                    SkipThisElement::from_xml(ctx, reader, e)?;
                 }
                 Ok(XmlEvent::Text(ref e)) => {
                     let period = match &*reader.decoder().decode(e)? {
@@ -362,22 +1180,76 @@ impl FromXml for Option<SyPeriod> {
     }
 }
 
+/// Parse a feed, `charset` is the one from HTTP headers,
+/// see the `charset` module for how the encoding is decided.
+///
 /// NOTE: This function doesn't check the syntax of feed, it only cares about performance
-pub fn parse<B: std::io::BufRead>(reader: B) -> quick_xml::Result<Rss> {
-    let mut reader = XmlReader::from_reader(reader);
+pub fn parse<B: std::io::BufRead>(reader: B, charset: Option<&str>) -> quick_xml::Result<Rss> {
+    let reader = charset::Utf8Reader::new(reader, charset)?;
+    parse_xml(XmlReader::from_reader(reader))
+}
+
+/// Like [`parse`], but tolerates stray `&` and `<`, unknown entities, unclosed elements
+/// and mismatched end tags.
+/// It's slower, use it only if [`parse`] failed.
+pub fn parse_recovering<B: std::io::BufRead>(
+    reader: B,
+    charset: Option<&str>,
+) -> quick_xml::Result<Rss> {
+    let reader = charset::Utf8Reader::new(reader, charset)?;
+    let mut reader = XmlReader::from_reader(recovery::RecoveringReader::new(reader));
+    reader.check_end_names(false);
+    let mut rss = parse_xml(reader)?;
+    rss.recovered = true;
+    Ok(rss)
+}
+
+fn parse_xml<B: std::io::BufRead>(mut reader: XmlReader<B>) -> quick_xml::Result<Rss> {
     reader.trim_text(true);
-    let bufs = BufPool::new(4, 512);
-    let mut buf = bufs.pop();
+    let ctx = ParseContext::new();
+    let mut buf = ctx.bufs.pop();
+    let mut base = None;
+    let mut format = Format::Unknown;
     loop {
         match reader.read_event_into(&mut buf) {
             Ok(XmlEvent::Start(ref e)) => {
-                match reader.decoder().decode(e.name().as_ref())?.as_ref() {
-                    "rss" => continue,
-                    "channel" | "feed" | "rdf:RDF" => {
-                        return Rss::from_xml(&bufs, &mut reader, e);
+                let (mut ns, name) = resolve_element(&ctx, &reader, e)?;
+                // RSS has no namespace, but some feeds declare one anyway,
+                // e.g. `<rss xmlns="http://backend.userland.com/rss2">`
+                if ns == Ns::Other
+                    && e.name().prefix().is_none()
+                    && matches!(&*name, "rss" | "channel")
+                {
+                    if let (ResolveResult::Bound(Namespace(uri)), _) =
+                        reader.resolve_element(e.name())
+                    {
+                        *ctx.feed_ns.borrow_mut() = Some(uri.to_vec());
+                        ns = Ns::Feed;
+                    }
+                }
+                match (ns, &*name) {
+                    (Ns::Feed, "rss") => {
+                        base = xml_base(&reader, e)?;
+                        format = match e.try_get_attribute("version")? {
+                            Some(v) if v.value.starts_with(b"0.9") => Format::Rss09x,
+                            _ => Format::Rss20,
+                        };
+                    }
+                    (Ns::Feed, "channel" | "feed") | (Ns::Rdf, "RDF") => {
+                        if format == Format::Unknown {
+                            format = detect_format(&reader, e)?;
+                        }
+                        let mut rss = Rss::from_xml(&ctx, &mut reader, e)?;
+                        if let Some(base) = base {
+                            rss.map_urls(|url| join_url(&base, url));
+                        }
+                        rss.format = format;
+                        rss.warnings = ctx.warnings.into_inner();
+                        rss.check();
+                        return Ok(rss);
                     }
                     _ => {
-                        SkipThisElement::from_xml(&bufs, &mut reader, e)?;
+                        SkipThisElement::from_xml(&ctx, &mut reader, e)?;
                     }
                 }
             }
@@ -389,42 +1261,196 @@ pub fn parse<B: std::io::BufRead>(reader: B) -> quick_xml::Result<Rss> {
     }
 }
 
-fn url_relative_to_absolute(link: &mut String, host: &str) {
-    match link.as_str() {
-        _ if link.starts_with("//") => {
-            let mut s = String::from("http:");
-            s.push_str(link);
-            *link = s;
+/// `xml:base` of current element
+fn xml_base<B>(reader: &XmlReader<B>, start: &BytesStart) -> quick_xml::Result<Option<String>> {
+    match start.try_get_attribute("xml:base")? {
+        Some(base) => Ok(Some(base.decode_and_unescape_value(reader)?.into_owned())),
+        None => Ok(None),
+    }
+}
+
+/// Resolve `reference` against `base` as RFC 3986 section 5.2 describes.
+/// `base` could be relative too, the result will be resolved against the feed URL later.
+fn join_url(base: &str, reference: &str) -> String {
+    if let Ok(base) = Url::parse(base) {
+        return base
+            .join(reference)
+            .map(String::from)
+            .unwrap_or_else(|_| reference.to_owned());
+    }
+    let r = UrlParts::split(reference);
+    if r.scheme.is_some() {
+        return reference.to_owned();
+    }
+    let b = UrlParts::split(base);
+    let (authority, path, query) = if r.authority.is_some() {
+        (r.authority, remove_dot_segments(r.path), r.query)
+    } else if r.path.is_empty() {
+        (b.authority, b.path.to_owned(), r.query.or(b.query))
+    } else if r.path.starts_with('/') {
+        (b.authority, remove_dot_segments(r.path), r.query)
+    } else {
+        let merged = match b.path.rfind('/') {
+            Some(i) => format!("{}{}", &b.path[..=i], r.path),
+            None if b.authority.is_some() => format!("/{}", r.path),
+            None => r.path.to_owned(),
+        };
+        (b.authority, remove_dot_segments(&merged), r.query)
+    };
+    UrlParts {
+        scheme: b.scheme,
+        authority,
+        path: &path,
+        query,
+        fragment: r.fragment,
+    }
+    .to_string()
+}
+
+/// Components of an URI reference, see RFC 3986 appendix B
+struct UrlParts<'a> {
+    scheme: Option<&'a str>,
+    authority: Option<&'a str>,
+    path: &'a str,
+    query: Option<&'a str>,
+    fragment: Option<&'a str>,
+}
+
+impl<'a> UrlParts<'a> {
+    fn split(s: &'a str) -> Self {
+        lazy_static! {
+            static ref RE: Regex =
+                Regex::new(r"^(?:([^:/?#]+):)?(?://([^/?#]*))?([^?#]*)(?:\?([^#]*))?(?:#(.*))?")
+                    .unwrap();
         }
-        _ if link.starts_with('/') => {
-            let mut s = String::from(host);
-            s.push_str(link);
-            *link = s;
+        // The regex matches any string
+        let caps = RE.captures(s).unwrap();
+        UrlParts {
+            scheme: caps.get(1).map(|m| m.as_str()),
+            authority: caps.get(2).map(|m| m.as_str()),
+            path: caps.get(3).map_or("", |m| m.as_str()),
+            query: caps.get(4).map(|m| m.as_str()),
+            fragment: caps.get(5).map(|m| m.as_str()),
         }
-        _ => (),
     }
 }
 
-pub fn fix_relative_url(mut rss: Rss, rss_link: &str) -> Rss {
-    lazy_static! {
-        static ref HOST: Regex = Regex::new(r"^(https?://[^/]+)").unwrap();
-    }
-    let rss_host = HOST
-        .captures(rss_link)
-        .map_or(rss_link, |r| r.get(0).unwrap().as_str());
-    match rss.link.as_str() {
-        "" | "/" => rss.link = rss_host.to_owned(),
-        _ => url_relative_to_absolute(&mut rss.link, rss_host),
+impl std::fmt::Display for UrlParts<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(scheme) = self.scheme {
+            write!(f, "{}:", scheme)?;
+        }
+        if let Some(authority) = self.authority {
+            write!(f, "//{}", authority)?;
+        }
+        f.write_str(self.path)?;
+        if let Some(query) = self.query {
+            write!(f, "?{}", query)?;
+        }
+        if let Some(fragment) = self.fragment {
+            write!(f, "#{}", fragment)?;
+        }
+        Ok(())
     }
-    for item in &mut rss.items {
-        if let Some(link) = item.link.as_mut() {
-            url_relative_to_absolute(link, rss_host);
+}
+
+/// RFC 3986 section 5.2.4, `..` never goes above the first segment
+fn remove_dot_segments(path: &str) -> String {
+    let absolute = path.starts_with('/');
+    let parts: Vec<&str> = path.split('/').collect();
+    let mut output = Vec::with_capacity(parts.len());
+    for (i, part) in parts.iter().enumerate() {
+        let last = i == parts.len() - 1;
+        match *part {
+            "." => {}
+            ".." => {
+                if output.len() > absolute as usize {
+                    output.pop();
+                }
+            }
+            part => {
+                output.push(part);
+                continue;
+            }
+        }
+        // `a/.` and `a/..` ends with a slash
+        if last {
+            output.push("");
         }
     }
+    output.join("/")
+}
 
+/// Resolve relative URLs against the feed URL, which should be the one after redirects
+pub fn fix_relative_url(mut rss: Rss, rss_link: &str) -> Rss {
+    let base = match Url::parse(rss_link) {
+        Ok(base) => base,
+        Err(_) => return rss,
+    };
+    if rss.link.is_empty() {
+        // Use the homepage
+        rss.link.push('/');
+    }
+    rss.map_urls(|url| {
+        base.join(url)
+            .map(String::from)
+            .unwrap_or_else(|_| url.to_owned())
+    });
     rss
 }
 
+/// Detect the format by the root element other than `rss`
+fn detect_format<B>(reader: &XmlReader<B>, start: &BytesStart) -> quick_xml::Result<Format> {
+    let (ns, name) = reader.resolve_element(start.name());
+    let format = match (ns, name.as_ref()) {
+        (ResolveResult::Bound(Namespace(b"http://purl.org/atom/ns#")), b"feed") => Format::Atom03,
+        (_, b"feed") => Format::Atom10,
+        (_, b"RDF") => match start.try_get_attribute("xmlns")? {
+            Some(xmlns) if &*xmlns.value == b"http://my.netscape.com/rdf/simple/0.9/" => {
+                Format::Rss09x
+            }
+            _ => Format::Rss10,
+        },
+        _ => Format::Unknown,
+    };
+    Ok(format)
+}
+
+/// States shared while parsing a feed
+struct ParseContext {
+    bufs: BufPool,
+    warnings: RefCell<Vec<Warning>>,
+    /// Default namespace declared by the root element of RSS, it means no namespace
+    feed_ns: RefCell<Option<Vec<u8>>>,
+}
+
+impl ParseContext {
+    fn new() -> Self {
+        ParseContext {
+            bufs: BufPool::new(4, 512),
+            warnings: RefCell::new(Vec::new()),
+            feed_ns: RefCell::new(None),
+        }
+    }
+
+    fn warn(&self, warning: Warning) {
+        self.warnings.borrow_mut().push(warning);
+    }
+
+    fn parse_date(
+        &self,
+        text: Option<String>,
+        parser: fn(&str) -> Option<DateTime<FixedOffset>>,
+    ) -> Option<DateTime<FixedOffset>> {
+        let text = text?;
+        let date = parser(&text);
+        if date.is_none() {
+            self.warn(Warning::InvalidDate(text));
+        }
+        date
+    }
+}
+
 struct BufPool {
     pool: Rc<RefCell<Vec<Vec<u8>>>>,
     capacity: usize,
@@ -480,32 +1506,93 @@ mod test {
 
     use super::*;
 
+    fn date(s: &str) -> Option<DateTime<FixedOffset>> {
+        Some(DateTime::parse_from_rfc3339(s).unwrap())
+    }
+
     #[test]
     fn encoding() {
         let s: &[u8] = &*include_bytes!("../tests/data/encoding.xml");
-        let r = parse(Cursor::new(s)).unwrap();
-        assert_eq!(r.title, "虎扑足球新闻")
+        let r = parse(Cursor::new(s), None).unwrap();
+        assert_eq!(r.title, "虎扑足球新闻");
+
+        // Without the XML declaration, only HTTP headers know the charset
+        let without_declaration = &s[s.iter().position(|&b| b == b'\n').unwrap()..];
+        let r = parse(Cursor::new(without_declaration), Some("gb2312")).unwrap();
+        assert_eq!(r.title, "虎扑足球新闻");
+        let r = parse(Cursor::new(without_declaration), Some("GBK")).unwrap();
+        assert_eq!(r.title, "虎扑足球新闻");
+        // HTTP headers take precedence over the XML declaration
+        let r = parse(Cursor::new(s), Some("utf-8")).unwrap();
+        assert_ne!(r.title, "虎扑足球新闻");
     }
 
     #[test]
     fn atom03() {
         let s = include_str!("../tests/data/atom_0.3.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None).unwrap();
         assert_eq!(
             r,
             Rss {
                 title: "atom_0.3.feed.title".into(),
+                format: Format::Atom03,
+                description: Some("atom_0.3.feed.tagline".into()),
+                generator: Some("atom_0.3.feed.generator".into()),
                 link: "atom_0.3.feed.link^href".into(),
                 items: vec![
                     Item {
                         title: Some("atom_0.3.feed.entry[0].title".into()),
                         link: Some("atom_0.3.feed.entry[0].link^href".into()),
                         id: Some("atom_0.3.feed.entry[0]^id".into()),
+                        summary: Some(Text::new(
+                            TextKind::Other("atom_0.3.feed.entry[0].summary^type".into()),
+                            "atom_0.3.feed.entry[0].summary".into()
+                        )),
+                        content: Some(Text::new(
+                            TextKind::Other("atom_0.3.feed.entry[0].content[0]^type".into()),
+                            "atom_0.3.feed.entry[0].content[0]".into()
+                        )),
+                        published: date("2000-01-01T01:00:00Z"),
+                        updated: date("2000-01-01T00:00:00Z"),
+                        attachments: vec![],
+                        authors: vec![Person {
+                            name: "atom_0.3.feed.entry[0].author.name".into(),
+                            email: Some("atom_0.3.feed.entry[0].author.email".into()),
+                            uri: Some("atom_0.3.feed.entry[0].author.url".into()),
+                        }],
+                        contributors: vec![Person {
+                            name: "atom_0.3.feed.entry[0].contributor.name".into(),
+                            email: Some("atom_0.3.feed.entry[0].contributor.email".into()),
+                            uri: Some("atom_0.3.feed.entry[0].contributor.url".into()),
+                        }],
+                        categories: vec![],
                     },
                     Item {
                         title: Some("atom_0.3.feed.entry[1].title".into()),
                         link: Some("atom_0.3.feed.entry[1].link^href".into()),
                         id: Some("atom_0.3.feed.entry[1]^id".into()),
+                        summary: Some(Text::new(
+                            TextKind::Other("atom_0.3.feed.entry[1].summary^type".into()),
+                            "atom_0.3.feed.entry[1].summary".into()
+                        )),
+                        content: Some(Text::new(
+                            TextKind::Other("atom_0.3.feed.entry[1].content[0]^type".into()),
+                            "atom_0.3.feed.entry[1].content[0]".into()
+                        )),
+                        published: date("2000-02-01T01:00:00Z"),
+                        updated: date("2000-02-01T00:00:00Z"),
+                        attachments: vec![],
+                        authors: vec![Person {
+                            name: "atom_0.3.feed.entry[1].author.name".into(),
+                            email: Some("atom_0.3.feed.entry[1].author.email".into()),
+                            uri: Some("atom_0.3.feed.entry[1].author.url".into()),
+                        }],
+                        contributors: vec![Person {
+                            name: "atom_0.3.feed.entry[1].contributor.name".into(),
+                            email: Some("atom_0.3.feed.entry[1].contributor.email".into()),
+                            uri: Some("atom_0.3.feed.entry[1].contributor.url".into()),
+                        }],
+                        categories: vec![],
                     },
                 ],
                 ..Rss::default()
@@ -516,11 +1603,15 @@ mod test {
     #[test]
     fn atom10() {
         let s = include_str!("../tests/data/atom_1.0.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None).unwrap();
         assert_eq!(
             r,
             Rss {
                 title: "atom_1.0.feed.title".into(),
+                format: Format::Atom10,
+                description: Some("atom_1.0.feed.tagline".into()),
+                language: Some("en-us".into()),
+                generator: Some("atom_1.0.feed.generator".into()),
                 link: "http://example.com/blog_plain".into(),
                 source: Some("http://example.com/blog/atom_1.0.xml".into()),
                 items: vec![
@@ -528,11 +1619,65 @@ mod test {
                         title: Some("atom_1.0.feed.entry[0].title".into()),
                         link: Some("http://example.com/blog/entry1_plain".into()),
                         id: Some("atom_1.0.feed.entry[0]^id".into()),
+                        summary: Some(Text::new(
+                            TextKind::Html,
+                            "atom_1.0.feed.entry[0].summary".into()
+                        )),
+                        content: Some(Text::new(
+                            TextKind::Html,
+                            "atom_1.0.feed.entry[0].content[0]".into()
+                        )),
+                        published: date("2000-01-01T01:00:00Z"),
+                        updated: date("2000-01-01T00:00:00Z"),
+                        attachments: vec![Attachment {
+                            url: "http://example.com/blog/enclosure1.gif".into(),
+                            mime_type: Some("image/gif".into()),
+                            length: None,
+                            duration: None,
+                        }],
+                        authors: vec![Person {
+                            name: "atom_1.0.feed.entry[0].author.name".into(),
+                            email: Some("author0@example.com".into()),
+                            uri: Some("http://example.com".into()),
+                        }],
+                        contributors: vec![Person {
+                            name: "atom_1.0.feed.entry[0].contributor.name".into(),
+                            email: Some("author1@example.com".into()),
+                            uri: Some("http://example.com".into()),
+                        }],
+                        categories: vec![],
                     },
                     Item {
                         title: Some("atom_1.0.feed.entry[1].title".into()),
                         link: Some("http://example.com/blog/entry2".into()),
                         id: Some("atom_1.0.feed.entry[1]^id".into()),
+                        summary: Some(Text::new(
+                            TextKind::Html,
+                            "atom_1.0.feed.entry[1].summary".into()
+                        )),
+                        content: Some(Text::new(
+                            TextKind::Html,
+                            "atom_1.0.feed.entry[1].content[0]".into()
+                        )),
+                        published: date("2000-02-01T01:00:00Z"),
+                        updated: date("2000-02-01T00:00:00Z"),
+                        attachments: vec![Attachment {
+                            url: "http://example.com/blog/enclosure2.gif".into(),
+                            mime_type: Some("image/gif".into()),
+                            length: None,
+                            duration: None,
+                        }],
+                        authors: vec![Person {
+                            name: "atom_1.0.feed.entry[1].author.name".into(),
+                            email: Some("author0@example.com".into()),
+                            uri: Some("http://example.com".into()),
+                        }],
+                        contributors: vec![Person {
+                            name: "atom_1.0.feed.entry[1].contributor.name".into(),
+                            email: Some("author1@example.com".into()),
+                            uri: Some("http://example.com".into()),
+                        }],
+                        categories: vec![],
                     },
                 ],
                 ..Rss::default()
@@ -543,22 +1688,41 @@ mod test {
     #[test]
     fn rss09() {
         let s = include_str!("../tests/data/rss_0.9.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None).unwrap();
         assert_eq!(
             r,
             Rss {
                 title: "rss_0.9.channel.title".into(),
+                format: Format::Rss09x,
+                description: Some("rss_0.9.channel.description".into()),
+                icon: Some("rss_0.9.image.url".into()),
                 link: "rss_0.9.channel.link".into(),
                 items: vec![
                     Item {
                         title: Some("rss_0.9.item[0].title".into()),
                         link: Some("rss_0.9.item[0].link".into()),
                         id: None,
+                        summary: None,
+                        content: None,
+                        published: None,
+                        updated: None,
+                        attachments: vec![],
+                        authors: vec![],
+                        contributors: vec![],
+                        categories: vec![],
                     },
                     Item {
                         title: Some("rss_0.9.item[1].title".into()),
                         link: Some("rss_0.9.item[1].link".into()),
                         id: None,
+                        summary: None,
+                        content: None,
+                        published: None,
+                        updated: None,
+                        attachments: vec![],
+                        authors: vec![],
+                        contributors: vec![],
+                        categories: vec![],
                     },
                 ],
                 ..Rss::default()
@@ -569,22 +1733,48 @@ mod test {
     #[test]
     fn rss091() {
         let s = include_str!("../tests/data/rss_0.91.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None).unwrap();
         assert_eq!(
             r,
             Rss {
                 title: "rss_0.91.channel.title".into(),
+                format: Format::Rss09x,
+                description: Some("rss_0.91.channel.description".into()),
+                icon: Some("rss_0.91.channel.image.url".into()),
+                language: Some("rss_0.91.channel.language".into()),
                 link: "rss_0.91.channel.link".into(),
                 items: vec![
                     Item {
                         title: Some("rss_0.91.channel.item[0].title".into()),
                         link: Some("rss_0.91.channel.item[0].link".into()),
                         id: None,
+                        summary: Some(Text::new(
+                            TextKind::Html,
+                            "rss_0.91.channel.item[0].description".into()
+                        )),
+                        content: None,
+                        published: None,
+                        updated: None,
+                        attachments: vec![],
+                        authors: vec![],
+                        contributors: vec![],
+                        categories: vec![],
                     },
                     Item {
                         title: Some("rss_0.91.channel.item[1].title".into()),
                         link: Some("rss_0.91.channel.item[1].link".into()),
                         id: None,
+                        summary: Some(Text::new(
+                            TextKind::Html,
+                            "rss_0.91.channel.item[1].description".into()
+                        )),
+                        content: None,
+                        published: None,
+                        updated: None,
+                        attachments: vec![],
+                        authors: vec![],
+                        contributors: vec![],
+                        categories: vec![],
                     },
                 ],
                 ..Rss::default()
@@ -595,22 +1785,80 @@ mod test {
     #[test]
     fn rss092() {
         let s = include_str!("../tests/data/rss_0.92.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None).unwrap();
         assert_eq!(
             r,
             Rss {
                 title: "rss_0.92.channel.title".into(),
+                format: Format::Rss09x,
+                description: Some("rss_0.92.channel.description".into()),
+                icon: Some("rss_0.92.channel.image.url".into()),
+                language: Some("rss_0.92.channel.language".into()),
                 link: "rss_0.92.channel.link".into(),
                 items: vec![
                     Item {
                         title: Some("rss_0.92.channel.item[0].title".into()),
                         link: Some("rss_0.92.channel.item[0].link".into()),
                         id: None,
+                        summary: Some(Text::new(
+                            TextKind::Html,
+                            "rss_0.92.channel.item[0].description".into()
+                        )),
+                        content: None,
+                        published: None,
+                        updated: None,
+                        attachments: vec![Attachment {
+                            url: "rss_0.92.channel.item[0].enclousure[0]^url".into(),
+                            mime_type: Some("rss_0.92.channel.item[0].enclousure[0]^type".into()),
+                            length: Some(100),
+                            duration: None,
+                        }],
+                        authors: vec![],
+                        contributors: vec![],
+                        categories: vec![
+                            Category {
+                                term: "rss_0.92.channel.item[0].category[0]".into(),
+                                label: None,
+                                scheme: Some("rss_0.92.channel.item[0].category[0]^domain".into()),
+                            },
+                            Category {
+                                term: "rss_0.92.channel.item[0].category[1]".into(),
+                                label: None,
+                                scheme: Some("rss_0.92.channel.item[0].category[1]^domain".into()),
+                            }
+                        ],
                     },
                     Item {
                         title: Some("rss_0.92.channel.item[1].title".into()),
                         link: Some("rss_0.92.channel.item[1].link".into()),
                         id: None,
+                        summary: Some(Text::new(
+                            TextKind::Html,
+                            "rss_0.92.channel.item[1].description".into()
+                        )),
+                        content: None,
+                        published: None,
+                        updated: None,
+                        attachments: vec![Attachment {
+                            url: "rss_0.92.channel.item[1].enclousure[0]^url".into(),
+                            mime_type: Some("rss_0.92.channel.item[1].enclousure[0]^type".into()),
+                            length: Some(100),
+                            duration: None,
+                        }],
+                        authors: vec![],
+                        contributors: vec![],
+                        categories: vec![
+                            Category {
+                                term: "rss_0.92.channel.item[1].category[0]".into(),
+                                label: None,
+                                scheme: Some("rss_0.92.channel.item[1].category[0]^domain".into()),
+                            },
+                            Category {
+                                term: "rss_0.92.channel.item[1].category[1]".into(),
+                                label: None,
+                                scheme: Some("rss_0.92.channel.item[1].category[1]^domain".into()),
+                            }
+                        ],
                     },
                 ],
                 ..Rss::default()
@@ -621,22 +1869,104 @@ mod test {
     #[test]
     fn rss093() {
         let s = include_str!("../tests/data/rss_0.93.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None).unwrap();
         assert_eq!(
             r,
             Rss {
                 title: "rss_0.93.channel.title".into(),
+                format: Format::Rss09x,
+                description: Some("rss_0.93.channel.description".into()),
+                icon: Some("rss_0.93.channel.image.url".into()),
+                language: Some("rss_0.93.channel.language".into()),
                 link: "rss_0.93.channel.link".into(),
                 items: vec![
                     Item {
                         title: Some("rss_0.93.channel.item[0].title".into()),
                         link: Some("rss_0.93.channel.item[0].link".into()),
                         id: None,
+                        summary: Some(Text::new(
+                            TextKind::Html,
+                            "rss_0.93.channel.item[0].description".into()
+                        )),
+                        content: None,
+                        published: date("2001-01-01T00:00:00Z"),
+                        updated: None,
+                        attachments: vec![
+                            Attachment {
+                                url: "rss_0.93.channel.item[0].enclousure[0]^url".into(),
+                                mime_type: Some(
+                                    "rss_0.93.channel.item[0].enclousure[0]^type".into()
+                                ),
+                                length: Some(100),
+                                duration: None,
+                            },
+                            Attachment {
+                                url: "rss_0.93.channel.item[0].enclousure[1]^url".into(),
+                                mime_type: Some(
+                                    "rss_0.93.channel.item[0].enclousure[1]^type".into()
+                                ),
+                                length: Some(100),
+                                duration: None,
+                            }
+                        ],
+                        authors: vec![],
+                        contributors: vec![],
+                        categories: vec![
+                            Category {
+                                term: "rss_0.93.channel.item[0].category[0]".into(),
+                                label: None,
+                                scheme: Some("rss_0.93.channel.item[0].category[0]^domain".into()),
+                            },
+                            Category {
+                                term: "rss_0.93.channel.item[0].category[1]".into(),
+                                label: None,
+                                scheme: Some("rss_0.93.channel.item[0].category[1]^domain".into()),
+                            }
+                        ],
                     },
                     Item {
                         title: Some("rss_0.93.channel.item[1].title".into()),
                         link: Some("rss_0.93.channel.item[1].link".into()),
                         id: None,
+                        summary: Some(Text::new(
+                            TextKind::Html,
+                            "rss_0.93.channel.item[1].description".into()
+                        )),
+                        content: None,
+                        published: date("2001-01-02T00:00:00Z"),
+                        updated: None,
+                        attachments: vec![
+                            Attachment {
+                                url: "rss_0.93.channel.item[1].enclousure[0]^url".into(),
+                                mime_type: Some(
+                                    "rss_0.93.channel.item[1].enclousure[0]^type".into()
+                                ),
+                                length: Some(100),
+                                duration: None,
+                            },
+                            Attachment {
+                                url: "rss_0.93.channel.item[1].enclousure[1]^url".into(),
+                                mime_type: Some(
+                                    "rss_0.93.channel.item[1].enclousure[1]^type".into()
+                                ),
+                                length: Some(100),
+                                duration: None,
+                            }
+                        ],
+                        authors: vec![],
+                        contributors: vec![],
+                        categories: vec![
+                            Category {
+                                term: "rss_0.93.channel.item[1].category[0]".into(),
+                                label: None,
+                                scheme: Some("rss_0.93.channel.item[1].category[0]^domain".into()),
+                            },
+                            Category {
+                                term: "rss_0.93.channel.item[1].category[1]".into(),
+                                label: None,
+                                scheme: Some("rss_0.93.channel.item[1].category[1]^domain".into()),
+                            }
+                        ],
                     },
                 ],
                 ..Rss::default()
@@ -647,11 +1977,16 @@ mod test {
     #[test]
     fn rss094() {
         let s = include_str!("../tests/data/rss_0.94.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None).unwrap();
         assert_eq!(
             r,
             Rss {
                 title: "rss_0.94.channel.title".into(),
+                format: Format::Rss09x,
+                description: Some("rss_0.94.channel.description".into()),
+                icon: Some("rss_0.94.channel.image.url".into()),
+                language: Some("rss_0.94.channel.language".into()),
+                generator: Some("rss_0.94.channel.generator".into()),
                 link: "rss_0.94.channel.link".into(),
                 ttl: Some(100),
                 items: vec![
@@ -659,11 +1994,97 @@ mod test {
                         title: Some("rss_0.94.channel.item[0].title".into()),
                         link: Some("rss_0.94.channel.item[0].link".into()),
                         id: Some("rss_0.94.channel.item[0].guid".into()),
+                        summary: Some(Text::new(
+                            TextKind::Html,
+                            "rss_0.94.channel.item[0].description".into()
+                        )),
+                        content: None,
+                        published: date("2001-01-01T00:00:00Z"),
+                        updated: None,
+                        attachments: vec![
+                            Attachment {
+                                url: "rss_0.94.channel.item[0].enclousure[0]^url".into(),
+                                mime_type: Some(
+                                    "rss_0.94.channel.item[0].enclousure[0]^type".into()
+                                ),
+                                length: Some(100),
+                                duration: None,
+                            },
+                            Attachment {
+                                url: "rss_0.94.channel.item[0].enclousure[1]^url".into(),
+                                mime_type: Some(
+                                    "rss_0.94.channel.item[0].enclousure[1]^type".into()
+                                ),
+                                length: Some(100),
+                                duration: None,
+                            }
+                        ],
+                        authors: vec![Person {
+                            name: "rss_0.94.channel.item[0].author".into(),
+                            email: None,
+                            uri: None,
+                        }],
+                        contributors: vec![],
+                        categories: vec![
+                            Category {
+                                term: "rss_0.94.channel.item[0].category[0]".into(),
+                                label: None,
+                                scheme: Some("rss_0.94.channel.item[0].category[0]^domain".into()),
+                            },
+                            Category {
+                                term: "rss_0.94.channel.item[0].category[1]".into(),
+                                label: None,
+                                scheme: Some("rss_0.94.channel.item[0].category[1]^domain".into()),
+                            }
+                        ],
                     },
                     Item {
                         title: Some("rss_0.94.channel.item[1].title".into()),
                         link: Some("rss_0.94.channel.item[1].link".into()),
                         id: Some("rss_0.94.channel.item[1].guid".into()),
+                        summary: Some(Text::new(
+                            TextKind::Html,
+                            "rss_0.94.channel.item[1].description".into()
+                        )),
+                        content: None,
+                        published: date("2001-01-02T00:00:00Z"),
+                        updated: None,
+                        attachments: vec![
+                            Attachment {
+                                url: "rss_0.94.channel.item[1].enclousure[0]^url".into(),
+                                mime_type: Some(
+                                    "rss_0.94.channel.item[1].enclousure[0]^type".into()
+                                ),
+                                length: Some(100),
+                                duration: None,
+                            },
+                            Attachment {
+                                url: "rss_0.94.channel.item[1].enclousure[1]^url".into(),
+                                mime_type: Some(
+                                    "rss_0.94.channel.item[1].enclousure[1]^type".into()
+                                ),
+                                length: Some(100),
+                                duration: None,
+                            }
+                        ],
+                        authors: vec![Person {
+                            name: "rss_0.94.channel.item[1].author".into(),
+                            email: None,
+                            uri: None,
+                        }],
+                        contributors: vec![],
+                        categories: vec![
+                            Category {
+                                term: "rss_0.94.channel.item[1].category[0]".into(),
+                                label: None,
+                                scheme: Some("rss_0.94.channel.item[1].category[0]^domain".into()),
+                            },
+                            Category {
+                                term: "rss_0.94.channel.item[1].category[1]".into(),
+                                label: None,
+                                scheme: Some("rss_0.94.channel.item[1].category[1]^domain".into()),
+                            }
+                        ],
                     },
                 ],
                 ..Rss::default()
@@ -674,22 +2095,47 @@ mod test {
     #[test]
     fn rss10() {
         let s = include_str!("../tests/data/rss_1.0.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None).unwrap();
         assert_eq!(
             r,
             Rss {
                 title: "rss_1.0.channel.title".into(),
+                format: Format::Rss10,
+                description: Some("rss_1.0.channel.description".into()),
+                icon: Some("rss_1.0.image.url".into()),
                 link: "rss_1.0.channel.link".into(),
                 items: vec![
                     Item {
                         title: Some("rss_1.0.item[0].title".into()),
                         link: Some("rss_1.0.item[0].link".into()),
                         id: None,
+                        summary: Some(Text::new(
+                            TextKind::Html,
+                            "rss_1.0.item[0].description".into()
+                        )),
+                        content: Some(Text::new(TextKind::Html, "rss_1.0.item[0].content".into())),
+                        published: None,
+                        updated: None,
+                        attachments: vec![],
+                        authors: vec![],
+                        contributors: vec![],
+                        categories: vec![],
                     },
                     Item {
                         title: Some("rss_1.0.item[1].title".into()),
                         link: Some("rss_1.0.item[1].link".into()),
                         id: None,
+                        summary: Some(Text::new(
+                            TextKind::Html,
+                            "rss_1.0.item[1].description".into()
+                        )),
+                        content: Some(Text::new(TextKind::Html, "rss_1.0.item[1].content".into())),
+                        published: None,
+                        updated: None,
+                        attachments: vec![],
+                        authors: vec![],
+                        contributors: vec![],
+                        categories: vec![],
                     },
                 ],
                 ..Rss::default()
@@ -700,11 +2146,16 @@ mod test {
     #[test]
     fn rss20() {
         let s = include_str!("../tests/data/rss_2.0.xml");
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None).unwrap();
         assert_eq!(
             r,
             Rss {
                 title: "rss_2.0.channel.title".into(),
+                format: Format::Rss20,
+                description: Some("rss_2.0.channel.description".into()),
+                icon: Some("rss_2.0.channel.image.url".into()),
+                language: Some("rss_2.0.channel.language".into()),
+                generator: Some("rss_2.0.channel.generator".into()),
                 link: "rss_2.0.channel.link".into(),
                 ttl: Some(100),
                 items: vec![
@@ -712,11 +2163,103 @@ mod test {
                         title: Some("rss_2.0.channel.item[0].title".into()),
                         link: Some("rss_2.0.channel.item[0].link".into()),
                         id: Some("rss_2.0.channel.item[0].guid".into()),
+                        summary: Some(Text::new(
+                            TextKind::Html,
+                            "rss_2.0.channel.item[0].description".into()
+                        )),
+                        content: Some(Text::new(
+                            TextKind::Html,
+                            "rss_2.0.channel.item[0].content".into()
+                        )),
+                        published: date("2001-01-01T00:00:00Z"),
+                        updated: None,
+                        attachments: vec![
+                            Attachment {
+                                url: "rss_2.0.channel.item[0].enclousure[0]^url".into(),
+                                mime_type: Some(
+                                    "rss_2.0.channel.item[0].enclousure[0]^type".into()
+                                ),
+                                length: Some(100),
+                                duration: None,
+                            },
+                            Attachment {
+                                url: "rss_2.0.channel.item[0].enclousure[1]^url".into(),
+                                mime_type: Some(
+                                    "rss_2.0.channel.item[0].enclousure[1]^type".into()
+                                ),
+                                length: Some(100),
+                                duration: None,
+                            }
+                        ],
+                        authors: vec![Person {
+                            name: "rss_2.0.channel.item[0].author".into(),
+                            email: None,
+                            uri: None,
+                        }],
+                        contributors: vec![],
+                        categories: vec![
+                            Category {
+                                term: "rss_2.0.channel.item[0].category[0]".into(),
+                                label: None,
+                                scheme: Some("rss_2.0.channel.item[0].category[0]^domain".into()),
+                            },
+                            Category {
+                                term: "rss_2.0.channel.item[0].category[1]".into(),
+                                label: None,
+                                scheme: Some("rss_2.0.channel.item[0].category[1]^domain".into()),
+                            }
+                        ],
                     },
                     Item {
                         title: Some("rss_2.0.channel.item[1].title".into()),
                         link: Some("rss_2.0.channel.item[1].link".into()),
                         id: Some("rss_2.0.channel.item[1].guid".into()),
+                        summary: Some(Text::new(
+                            TextKind::Html,
+                            "rss_2.0.channel.item[1].description".into()
+                        )),
+                        content: Some(Text::new(
+                            TextKind::Html,
+                            "rss_2.0.channel.item[1].content".into()
+                        )),
+                        published: date("2001-01-02T00:00:00Z"),
+                        updated: None,
+                        attachments: vec![
+                            Attachment {
+                                url: "rss_2.0.channel.item[1].enclousure[0]^url".into(),
+                                mime_type: Some(
+                                    "rss_2.0.channel.item[1].enclousure[0]^type".into()
+                                ),
+                                length: Some(100),
+                                duration: None,
+                            },
+                            Attachment {
+                                url: "rss_2.0.channel.item[1].enclousure[1]^url".into(),
+                                mime_type: Some(
+                                    "rss_2.0.channel.item[1].enclousure[1]^type".into()
+                                ),
+                                length: Some(100),
+                                duration: None,
+                            }
+                        ],
+                        authors: vec![Person {
+                            name: "rss_2.0.channel.item[1].author".into(),
+                            email: None,
+                            uri: None,
+                        }],
+                        contributors: vec![],
+                        categories: vec![
+                            Category {
+                                term: "rss_2.0.channel.item[1].category[0]".into(),
+                                label: None,
+                                scheme: Some("rss_2.0.channel.item[1].category[0]^domain".into()),
+                            },
+                            Category {
+                                term: "rss_2.0.channel.item[1].category[1]".into(),
+                                label: None,
+                                scheme: Some("rss_2.0.channel.item[1].category[1]^domain".into()),
+                            }
+                        ],
                     },
                 ],
                 ..Rss::default()
@@ -732,10 +2275,32 @@ mod test {
 <atom:link href="self link" rel="self" />
 </channel>
 </rss>"#;
-        let r = parse(Cursor::new(s)).unwrap();
+        let r = parse(Cursor::new(s), None).unwrap();
         assert_eq!(r.source, Some("self link".into()));
     }
 
+    #[test]
+    fn rss_default_namespace() {
+        let s: &[u8] = &*include_bytes!("../tests/data/rss_2.0_default_ns.xml");
+        let r = parse(s, None).unwrap();
+        assert_eq!(r.format, Format::Rss20);
+        assert_eq!(r.title, "rss_2.0_default_ns.channel.title");
+        assert_eq!(
+            r.description,
+            Some("rss_2.0_default_ns.channel.description".into())
+        );
+        assert_eq!(r.items.len(), 1);
+        assert_eq!(r.items[0].link, Some("http://example.com/item0".into()));
+        assert_eq!(
+            r.items[0].authors[0].name,
+            "rss_2.0_default_ns.item[0].creator"
+        );
+
+        // Other roots in unknown namespaces are still not feeds
+        let input = r#"<html xmlns="http://www.w3.org/1999/xhtml"><title>t</title></html>"#;
+        assert!(parse(Cursor::new(input), None).is_err());
+    }
+
     #[test]
     fn atom_link_parsing() {
         let data = [
@@ -743,7 +2308,10 @@ mod test {
             r#"<link href="alternate href" rel="alternate" />"#,
             r#"<link href="self href" rel="self" />"#,
             r#"<link href="hub href" rel="hub" />"#,
+            r#"<link href="next href" rel="next" />"#,
+            r#"<link href="archive href" rel="prev-archive" />"#,
             r#"<link href="other href" rel="other" />"#,
+            r#"<link href="enclosure href" rel="enclosure" type="audio/mpeg" length=" 42 " />"#,
             r#"<link />"#,
         ];
         let results = vec![
@@ -751,10 +2319,18 @@ mod test {
             Some(AtomLink::Alternate("alternate href".into())),
             Some(AtomLink::Source("self href".into())),
             Some(AtomLink::Hub("hub href".into())),
+            Some(AtomLink::Next("next href".into())),
+            Some(AtomLink::PrevArchive("archive href".into())),
             Some(AtomLink::Other(
                 "other href".into(),
                 Cow::Owned("other".into()),
             )),
+            Some(AtomLink::Enclosure(Attachment {
+                url: "enclosure href".into(),
+                mime_type: Some("audio/mpeg".into()),
+                length: Some(42),
+                duration: None,
+            })),
             None,
         ];
         for (data, result) in data.iter().zip(results) {
@@ -769,7 +2345,7 @@ mod test {
 
     #[test]
     fn empty_input() {
-        let r = parse(Cursor::new(&[])).unwrap_err();
+        let r = parse(Cursor::new(&[]), None).unwrap_err();
         assert!(matches!(r, quick_xml::Error::UnexpectedEof(s) if s == "feed" ))
     }
 
@@ -782,7 +2358,7 @@ mod test {
 <sy:updateFrequency>6</sy:updateFrequency>
 </channel>
 </rss>"#;
-        let sy_output = parse(Cursor::new(sy_input)).unwrap();
+        let sy_output = parse(Cursor::new(sy_input), None).unwrap();
         assert_eq!(sy_output.ttl, Some(10));
     }
 
@@ -794,7 +2370,7 @@ mod test {
 <sy:updatePeriod>daily</sy:updatePeriod>
 </channel>
 </rss>"#;
-        let sy_output = parse(Cursor::new(sy_input)).unwrap();
+        let sy_output = parse(Cursor::new(sy_input), None).unwrap();
         assert_eq!(sy_output.ttl, Some(60 * 24));
     }
 
@@ -808,7 +2384,7 @@ mod test {
 </ttl>
 </channel>
 </rss>"#;
-        let output = parse(Cursor::new(input)).unwrap();
+        let output = parse(Cursor::new(input), None).unwrap();
         assert_eq!(output.ttl, Some(42));
     }
 
@@ -822,7 +2398,7 @@ mod test {
 <ttl>42</ttl>
 </channel>
 </rss>"#;
-        let output = parse(Cursor::new(input)).unwrap();
+        let output = parse(Cursor::new(input), None).unwrap();
         assert_eq!(output.ttl, Some(42));
     }
 
@@ -839,7 +2415,7 @@ mod test {
 </rss>"#,
             CHARACTERS
         );
-        let r = parse(Cursor::new(input)).unwrap();
+        let r = parse(Cursor::new(input), None).unwrap();
         assert_eq!(
             r,
             Rss {
@@ -847,8 +2423,459 @@ mod test {
                 link: "".into(),
                 ttl: None,
                 source: None,
+                hub: None,
+                next_page: None,
+                prev_archive: None,
+                description: None,
+                icon: None,
+                language: None,
+                generator: None,
+                recovered: false,
+                format: Format::Rss20,
+                warnings: vec![],
                 items: vec![],
             }
         );
     }
+
+    #[test]
+    fn atom_xhtml_content() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<entry>
+<summary>1 &lt; 2</summary>
+<content type="xhtml">
+<div xmlns="http://www.w3.org/1999/xhtml"><p>Hello <b>world</b> &amp; all<br/></p></div>
+</content>
+</entry>
+</feed>"#;
+        let r = parse(Cursor::new(input), None).unwrap();
+        assert_eq!(
+            r.items[0].summary,
+            Some(Text::new(TextKind::Text, "1 < 2".into()))
+        );
+        assert_eq!(
+            r.items[0].content,
+            Some(Text::new(
+                TextKind::Xhtml,
+                "<p>Hello <b>world</b> &amp; all<br/></p>".into()
+            ))
+        );
+    }
+
+    #[test]
+    fn rfc822_date_variants() {
+        let expected = date("2001-01-02T03:04:05Z");
+        let data = [
+            "Tue, 02 Jan 2001 03:04:05 GMT",
+            "Tue, 02 Jan 2001 03:04:05 +0000",
+            "Tue, 2 Jan 2001 03:04:05 UTC",
+            // wrong weekday
+            "Mon, 02 Jan 2001 03:04:05 GMT",
+            "Tuesday, 02 January 2001 03:04:05 GMT",
+            "Tue 02 jan 01 03:04:05 Z",
+            "02 Jan 2001 03:04:05",
+            "Tue, 02 Jan 2001 11:04:05 +08:00",
+            "Tue, 02 Jan 2001 11:04:05 +0800 (CST)",
+            "Mon, 01 Jan 2001 22:04:05 EST",
+            "  Tue, 02 Jan 2001 03:04:05.000 GMT\n",
+            "2001-01-02T03:04:05Z",
+        ];
+        for s in data {
+            assert_eq!(parse_rfc822_date(s), expected, "{}", s);
+        }
+        assert_eq!(
+            parse_rfc822_date("Tue, 02 Jan 2001 03:04 GMT"),
+            date("2001-01-02T03:04:00Z")
+        );
+        assert_eq!(parse_rfc822_date(""), None);
+        assert_eq!(parse_rfc822_date("yesterday"), None);
+        assert_eq!(parse_rfc822_date("Tue, 32 Jan 2001 03:04:05 GMT"), None);
+    }
+
+    #[test]
+    fn w3c_date_variants() {
+        let expected = date("2001-01-02T03:04:05Z");
+        let data = [
+            "2001-01-02T03:04:05Z",
+            "2001-01-02T03:04:05.000Z",
+            "2001-01-02T11:04:05+08:00",
+            "2001-01-02T11:04:05+0800",
+            "2001-01-02 03:04:05Z",
+            "2001-01-02T03:04:05",
+        ];
+        for s in data {
+            assert_eq!(parse_w3c_date(s), expected, "{}", s);
+        }
+        assert_eq!(
+            parse_w3c_date("2001-01-02T03:04Z"),
+            date("2001-01-02T03:04:00Z")
+        );
+        assert_eq!(parse_w3c_date("2001-01-02"), date("2001-01-02T00:00:00Z"));
+        assert_eq!(parse_w3c_date("not a date"), None);
+    }
+
+    #[test]
+    fn item_dates() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:dc="http://purl.org/dc/elements/1.1/">
+<channel>
+<item><dc:date>2001-01-02T03:04:05Z</dc:date></item>
+<item><pubDate>Tue, 02 Jan 2001 03:04:05 GMT</pubDate><dc:date>2000-01-01</dc:date></item>
+<item><pubDate>invalid date</pubDate></item>
+</channel>
+</rss>"#;
+        let r = parse(Cursor::new(input), None).unwrap();
+        let expected = date("2001-01-02T03:04:05Z");
+        assert_eq!(r.items[0].published, expected);
+        assert_eq!(r.items[1].published, expected);
+        assert_eq!(r.items[2].published, None);
+    }
+
+    #[test]
+    fn media_rss() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:media="http://search.yahoo.com/mrss/" xmlns:itunes="http://www.itunes.com/dtds/podcast-1.0.dtd">
+<channel>
+<item>
+<enclosure url="/episode.mp3" length="1024" type="audio/mpeg" />
+<itunes:duration>01:02:03</itunes:duration>
+</item>
+<item>
+<media:group>
+<media:content url="http://example.com/a.mp4" type="video/mp4" fileSize="2048" duration="60">
+<media:title>title</media:title>
+</media:content>
+<media:thumbnail url="http://example.com/a.jpg" />
+</media:group>
+<media:thumbnail url="http://example.com/b.jpg"></media:thumbnail>
+</item>
+</channel>
+</rss>"#;
+        let r = parse(Cursor::new(input), None).unwrap();
+        let r = fix_relative_url(r, "https://example.com/feed.xml");
+        assert_eq!(
+            r.items[0].attachments,
+            vec![Attachment {
+                url: "https://example.com/episode.mp3".into(),
+                mime_type: Some("audio/mpeg".into()),
+                length: Some(1024),
+                duration: Some(3723),
+            }]
+        );
+        assert_eq!(
+            r.items[1].attachments,
+            vec![
+                Attachment {
+                    url: "http://example.com/a.mp4".into(),
+                    mime_type: Some("video/mp4".into()),
+                    length: Some(2048),
+                    duration: Some(60),
+                },
+                Attachment {
+                    url: "http://example.com/a.jpg".into(),
+                    mime_type: None,
+                    length: None,
+                    duration: None,
+                },
+                Attachment {
+                    url: "http://example.com/b.jpg".into(),
+                    mime_type: None,
+                    length: None,
+                    duration: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rss_authors() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:dc="http://purl.org/dc/elements/1.1/">
+<channel>
+<item>
+<author>someone@example.com (Some One)</author>
+<dc:creator>Another One</dc:creator>
+</item>
+</channel>
+</rss>"#;
+        let r = parse(Cursor::new(input), None).unwrap();
+        assert_eq!(
+            r.items[0].authors,
+            vec![
+                Person {
+                    name: "Some One".into(),
+                    email: Some("someone@example.com".into()),
+                    uri: None,
+                },
+                Person {
+                    name: "Another One".into(),
+                    email: None,
+                    uri: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn atom_categories() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<entry>
+<category term="rust" />
+<category term="rss" label="RSS" scheme="http://example.com/tags"></category>
+</entry>
+</feed>"#;
+        let r = parse(Cursor::new(input), None).unwrap();
+        let categories = &r.items[0].categories;
+        assert_eq!(categories.len(), 2);
+        assert_eq!(categories[0].term, "rust");
+        assert_eq!(
+            categories[1],
+            Category {
+                term: "rss".into(),
+                label: Some("RSS".into()),
+                scheme: Some("http://example.com/tags".into()),
+            }
+        );
+    }
+
+    #[test]
+    fn atom_icon_and_logo() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<logo>logo</logo>
+<icon>icon</icon>
+</feed>"#;
+        let r = parse(Cursor::new(input), None).unwrap();
+        assert_eq!(r.icon, Some("icon".into()));
+
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<logo>logo</logo>
+</feed>"#;
+        let r = parse(Cursor::new(input), None).unwrap();
+        assert_eq!(r.icon, Some("logo".into()));
+
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<icon>/favicon.png</icon>
+</feed>"#;
+        let r = parse(Cursor::new(input), None).unwrap();
+        let r = fix_relative_url(r, "https://example.com/blog/feed.xml");
+        assert_eq!(r.icon, Some("https://example.com/favicon.png".into()));
+    }
+
+    #[test]
+    fn namespace_prefixes() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<r:RDF xmlns:r="http://www.w3.org/1999/02/22-rdf-syntax-ns#"
+       xmlns="http://purl.org/rss/1.0/"
+       xmlns:creator="http://purl.org/dc/elements/1.1/"
+       xmlns:body="http://purl.org/rss/1.0/modules/content/">
+<channel><title>RDF</title></channel>
+<item>
+<title>item</title>
+<creator:creator>someone</creator:creator>
+<body:encoded>content</body:encoded>
+</item>
+</r:RDF>"#;
+        let r = parse(Cursor::new(input), None).unwrap();
+        assert_eq!(r.title, "RDF");
+        assert_eq!(r.items[0].title, Some("item".into()));
+        assert_eq!(r.items[0].authors[0].name, "someone");
+        assert_eq!(
+            r.items[0].content,
+            Some(Text::new(TextKind::Html, "content".into()))
+        );
+
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<a:feed xmlns:a="http://www.w3.org/2005/Atom">
+<a:title>Atom</a:title>
+<a:entry><a:link href="link" /></a:entry>
+</a:feed>"#;
+        let r = parse(Cursor::new(input), None).unwrap();
+        assert_eq!(r.title, "Atom");
+        assert_eq!(r.items[0].link, Some("link".into()));
+    }
+
+    #[test]
+    fn foreign_namespaces() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:foo="http://example.com/foo">
+<channel>
+<title>RSS</title>
+<foo:title>foreign</foo:title>
+<item>
+<title>item</title>
+<link>link</link>
+<foo:title>foreign</foo:title>
+<foo:link>foreign</foo:link>
+</item>
+</channel>
+</rss>"#;
+        let r = parse(Cursor::new(input), None).unwrap();
+        assert_eq!(r.title, "RSS");
+        assert_eq!(r.items[0].title, Some("item".into()));
+        assert_eq!(r.items[0].link, Some("link".into()));
+
+        // Well-known prefixes used without declaration
+        let input = r#"<rss version="2.0"><channel><item>
+<dc:creator>someone</dc:creator>
+</item></channel></rss>"#;
+        let r = parse(Cursor::new(input), None).unwrap();
+        assert_eq!(r.items[0].authors[0].name, "someone");
+    }
+
+    #[test]
+    fn recovery() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0">
+<channel>
+<title>Q&A &nbsp;&mdash; &copy2024 &foo;</title>
+<item><title>a</titel><link>http://example.com/?a=1&b=2</link></item>
+<item><title>b</item>
+<item><title>c</title><description>1 < 2<br>3</description></item>
+</channel>
+</rss>"#;
+        assert!(parse(Cursor::new(input), None).is_err());
+        let r = parse_recovering(Cursor::new(input), None).unwrap();
+        assert!(r.recovered);
+        assert_eq!(r.title, "Q&A \u{a0}\u{2014} &copy2024 &foo;");
+        assert_eq!(r.items[0].title, Some("a".into()));
+        assert_eq!(r.items[0].link, Some("http://example.com/?a=1&b=2".into()));
+        assert_eq!(r.items.len(), 3);
+        assert_eq!(r.items[1].title, Some("b".into()));
+        assert_eq!(r.items[2].title, Some("c".into()));
+
+        // HTML entities are fine in strict mode
+        let input = "<rss><channel><title>&nbsp;&mdash;</title></channel></rss>";
+        let r = parse(Cursor::new(input), None).unwrap();
+        assert_eq!(r.title, "\u{a0}\u{2014}");
+        assert!(!r.recovered);
+    }
+
+    #[test]
+    fn relative_urls() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0">
+<channel>
+<link>/</link>
+<item><link>posts/1.html</link></item>
+<item><link>../a</link></item>
+<item><link>//cdn.example.com/b</link></item>
+<item><link>?page=2</link></item>
+<item><link>mailto:someone@example.com</link></item>
+</channel>
+</rss>"#;
+        let r = parse(Cursor::new(input), None).unwrap();
+        let r = fix_relative_url(r, "https://example.com/blog/feed.xml");
+        assert_eq!(r.link, "https://example.com/");
+        let links: Vec<_> = r.items.iter().map(|item| item.link.as_deref()).collect();
+        assert_eq!(
+            links,
+            vec![
+                Some("https://example.com/blog/posts/1.html"),
+                Some("https://example.com/a"),
+                Some("https://cdn.example.com/b"),
+                Some("https://example.com/blog/feed.xml?page=2"),
+                Some("mailto:someone@example.com"),
+            ]
+        );
+    }
+
+    #[test]
+    fn paging_links() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<link rel="next" href="?page=2" />
+<link rel="prev-archive" href="archive/2023.xml" />
+</feed>"#;
+        let r = parse(Cursor::new(input), None).unwrap();
+        let r = fix_relative_url(r, "https://example.com/blog/feed.xml");
+        assert_eq!(
+            r.next_page.as_deref(),
+            Some("https://example.com/blog/feed.xml?page=2")
+        );
+        assert_eq!(
+            r.prev_archive.as_deref(),
+            Some("https://example.com/blog/archive/2023.xml")
+        );
+    }
+
+    #[test]
+    fn xml_base() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom" xml:base="/blog/">
+<link href="./" />
+<entry xml:base="2024/">
+<link href="post.html" />
+<link rel="enclosure" href="../audio.mp3" />
+</entry>
+<entry>
+<link xml:base="http://example.org/x/" href="y" />
+</entry>
+<entry xml:base="http://example.net/">
+<link href="z" />
+</entry>
+</feed>"#;
+        let r = parse(Cursor::new(input), None).unwrap();
+        assert_eq!(r.link, "/blog/");
+        assert_eq!(r.items[0].link, Some("/blog/2024/post.html".into()));
+        assert_eq!(r.items[0].attachments[0].url, "/blog/audio.mp3");
+        assert_eq!(r.items[1].link, Some("http://example.org/x/y".into()));
+        assert_eq!(r.items[2].link, Some("http://example.net/z".into()));
+
+        let r = fix_relative_url(r, "https://example.com/feed.xml");
+        assert_eq!(r.link, "https://example.com/blog/");
+        assert_eq!(
+            r.items[0].link,
+            Some("https://example.com/blog/2024/post.html".into())
+        );
+    }
+
+    #[test]
+    fn join_relative_urls() {
+        for (base, reference, expected) in [
+            ("a/b", "c", "a/c"),
+            ("a/b/", "../c", "a/c"),
+            ("a/", "../../c", "c"),
+            ("/a/b", "./", "/a/"),
+            ("/a/b", "..", "/"),
+            ("//host/a", "b?q#f", "//host/b?q#f"),
+            ("/a?q", "", "/a?q"),
+            ("/a", "//host", "//host"),
+            ("/a", "https://host/", "https://host/"),
+        ] {
+            assert_eq!(
+                join_url(base, reference),
+                expected,
+                "{} {}",
+                base,
+                reference
+            );
+        }
+    }
+
+    #[test]
+    fn warnings() {
+        let input = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0">
+<channel>
+<ttl>an hour</ttl>
+<item><title>a</title><pubDate>yesterday</pubDate></item>
+<item><guid>b</guid></item>
+</channel>
+</rss>"#;
+        let r = parse(Cursor::new(input), None).unwrap();
+        assert_eq!(
+            r.warnings,
+            vec![
+                Warning::InvalidTtl("an hour".into()),
+                Warning::InvalidDate("yesterday".into()),
+                Warning::MissingTitle,
+                Warning::UnidentifiableItem(0),
+            ]
+        );
+    }
 }
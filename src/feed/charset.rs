@@ -0,0 +1,150 @@
+//! Transcoding feeds into UTF-8 before parsing.
+//!
+//! The encoding is decided in this order:
+//! 1. Byte order mark
+//! 2. `charset` of the HTTP `Content-Type` header
+//! 3. `encoding` of the XML declaration
+//! 4. UTF-8
+
+use std::io::{self, BufRead, Cursor, Read};
+
+use encoding_rs::{CoderResult, Decoder, Encoding, UTF_8};
+use lazy_static::lazy_static;
+use regex::bytes::Regex;
+
+/// The XML declaration must be at the very beginning,
+/// 1 KiB is enough even if there are some leading whitespaces
+const SNIFF_LEN: usize = 1024;
+const OUTPUT_LEN: usize = 8 * 1024;
+
+pub struct Utf8Reader<R> {
+    inner: io::Chain<Cursor<Vec<u8>>, R>,
+    decoder: Decoder,
+    output: Box<[u8]>,
+    output_len: usize,
+    pos: usize,
+    finished: bool,
+}
+
+impl<R: BufRead> Utf8Reader<R> {
+    pub fn new(mut inner: R, charset: Option<&str>) -> io::Result<Self> {
+        let mut head = Vec::with_capacity(SNIFF_LEN);
+        (&mut inner).take(SNIFF_LEN as u64).read_to_end(&mut head)?;
+
+        let encoding = match Encoding::for_bom(&head) {
+            Some((encoding, bom_len)) => {
+                head.drain(..bom_len);
+                encoding
+            }
+            None => charset
+                .and_then(|label| Encoding::for_label(label.trim().as_bytes()))
+                .or_else(|| declared_encoding(&head))
+                .unwrap_or(UTF_8),
+        };
+
+        Ok(Utf8Reader {
+            inner: Cursor::new(head).chain(inner),
+            decoder: encoding.new_decoder_without_bom_handling(),
+            output: vec![0; OUTPUT_LEN].into_boxed_slice(),
+            output_len: 0,
+            pos: 0,
+            finished: false,
+        })
+    }
+}
+
+/// `encoding` of the XML declaration
+fn declared_encoding(head: &[u8]) -> Option<&'static Encoding> {
+    lazy_static! {
+        static ref RE: Regex =
+            Regex::new(r#"^\s*<\?xml[^>]*?\sencoding\s*=\s*["']([A-Za-z0-9._:\-]+)["']"#).unwrap();
+    }
+    let label = RE.captures(head)?.get(1)?.as_bytes();
+    // The declaration is readable as ASCII, so it can't be UTF-16
+    Encoding::for_label(label).filter(|encoding| encoding.is_ascii_compatible())
+}
+
+impl<R: BufRead> Read for Utf8Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for Utf8Reader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos == self.output_len && !self.finished {
+            let input = self.inner.fill_buf()?;
+            let last = input.is_empty();
+            let (result, read, written, _) =
+                self.decoder.decode_to_utf8(input, &mut self.output, last);
+            self.inner.consume(read);
+            self.output_len = written;
+            self.pos = 0;
+            self.finished = last && result == CoderResult::InputEmpty;
+        }
+        Ok(&self.output[self.pos..self.output_len])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.output_len);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn transcode(input: &[u8], charset: Option<&str>) -> String {
+        let mut output = String::new();
+        Utf8Reader::new(input, charset)
+            .unwrap()
+            .read_to_string(&mut output)
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn precedence() {
+        let gbk = b"<?xml version=\"1.0\" encoding=\"gbk\"?><t>\xc4\xe3\xba\xc3</t>";
+        assert_eq!(
+            transcode(gbk, None),
+            r#"<?xml version="1.0" encoding="gbk"?><t>你好</t>"#
+        );
+
+        let utf8 = "<?xml version=\"1.0\" encoding=\"gbk\"?><t>你好</t>";
+        // Header wins over declaration
+        assert_eq!(transcode(utf8.as_bytes(), Some("utf-8")), utf8);
+        // BOM wins over header
+        let with_bom = [b"\xef\xbb\xbf", utf8.as_bytes()].concat();
+        assert_eq!(transcode(&with_bom, Some("gbk")), utf8);
+
+        // Header only
+        assert_eq!(
+            transcode(b"<t>\xc4\xe3\xba\xc3</t>", Some("gbk")),
+            "<t>你好</t>"
+        );
+        // Nothing at all
+        assert_eq!(transcode("<t>你好</t>".as_bytes(), None), "<t>你好</t>");
+    }
+
+    #[test]
+    fn utf16() {
+        let input: Vec<u8> = "\u{feff}<t>你好</t>"
+            .encode_utf16()
+            .flat_map(|c| c.to_le_bytes())
+            .collect();
+        assert_eq!(transcode(&input, Some("gbk")), "<t>你好</t>");
+    }
+
+    #[test]
+    fn split_sequences() {
+        // Longer than SNIFF_LEN and OUTPUT_LEN, multi-byte sequences cross boundaries
+        let input = [b"<t>".as_slice(), &b"\xc4\xe3".repeat(OUTPUT_LEN)].concat();
+        let expected = format!("<t>{}", "你".repeat(OUTPUT_LEN));
+        assert_eq!(transcode(&input, Some("gbk")), expected);
+    }
+}
@@ -0,0 +1,459 @@
+//! JSON Feed 1.0 and 1.1
+//!
+//! https://www.jsonfeed.org/version/1.1/
+
+use serde::{Deserialize, Deserializer};
+use thiserror::Error;
+
+use super::{
+    parse_w3c_date, Attachment, Category, Format, Item, Person, Rss, Text, TextKind, Warning,
+};
+
+const VERSION_PREFIXES: [&str; 2] = [
+    "https://jsonfeed.org/version/1",
+    "http://jsonfeed.org/version/1",
+];
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("invalid json: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("unsupported JSON Feed version: {0}")]
+    UnsupportedVersion(String),
+}
+
+pub fn parse<R: std::io::Read>(reader: R) -> Result<Rss, Error> {
+    let feed: Feed = serde_json::from_reader(reader)?;
+    // 1 and 1.1 are compatible, and future 1.x should be too
+    let version = feed.version.trim_end_matches('/');
+    let supported = VERSION_PREFIXES.iter().any(|prefix| {
+        version
+            .strip_prefix(prefix)
+            .is_some_and(|minor| minor.is_empty() || minor.starts_with('.'))
+    });
+    if !supported {
+        return Err(Error::UnsupportedVersion(feed.version));
+    }
+    let mut rss = Rss::from(feed);
+    rss.check();
+    Ok(rss)
+}
+
+#[derive(Deserialize)]
+struct Feed {
+    version: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    home_page_url: String,
+    feed_url: Option<String>,
+    description: Option<String>,
+    icon: Option<String>,
+    favicon: Option<String>,
+    language: Option<String>,
+    #[serde(default)]
+    hubs: Vec<FeedHub>,
+    next_url: Option<String>,
+    #[serde(default)]
+    items: Vec<FeedItem>,
+}
+
+#[derive(Deserialize)]
+struct FeedHub {
+    #[serde(rename = "type")]
+    kind: String,
+    url: String,
+}
+
+impl From<Feed> for Rss {
+    fn from(feed: Feed) -> Self {
+        let mut warnings = Vec::new();
+        let items = feed
+            .items
+            .into_iter()
+            .map(|item| item.into_item(&mut warnings))
+            .collect();
+        Rss {
+            title: feed.title,
+            link: feed.home_page_url,
+            source: feed.feed_url,
+            hub: feed
+                .hubs
+                .into_iter()
+                .find(|hub| hub.kind.eq_ignore_ascii_case("websub"))
+                .map(|hub| hub.url),
+            next_page: feed.next_url,
+            prev_archive: None,
+            ttl: None,
+            description: feed.description,
+            icon: feed.icon.or(feed.favicon),
+            language: feed.language,
+            generator: None,
+            recovered: false,
+            format: Format::JsonFeed,
+            warnings,
+            items,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct FeedItem {
+    /// Some publishers use numbers
+    #[serde(default, deserialize_with = "deserialize_id")]
+    id: Option<String>,
+    url: Option<String>,
+    external_url: Option<String>,
+    title: Option<String>,
+    summary: Option<String>,
+    content_html: Option<String>,
+    content_text: Option<String>,
+    image: Option<String>,
+    date_published: Option<String>,
+    date_modified: Option<String>,
+    #[serde(default)]
+    attachments: Vec<FeedAttachment>,
+    /// JSON Feed 1.1
+    #[serde(default)]
+    authors: Vec<Author>,
+    /// JSON Feed 1.0, deprecated in 1.1
+    author: Option<Author>,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+fn deserialize_id<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Id {
+        String(String),
+        Number(serde_json::Number),
+    }
+    Ok(Option::<Id>::deserialize(deserializer)?.map(|id| match id {
+        Id::String(s) => s,
+        Id::Number(n) => n.to_string(),
+    }))
+}
+
+impl FeedItem {
+    /// Invalid dates are left out with a warning, like XML feeds
+    fn into_item(self, warnings: &mut Vec<Warning>) -> Item {
+        let mut parse_date = |text: Option<String>| {
+            let text = text?;
+            let date = parse_w3c_date(&text);
+            if date.is_none() {
+                warnings.push(Warning::InvalidDate(text));
+            }
+            date
+        };
+        let content = self
+            .content_html
+            .map(|html| Text::new(TextKind::Html, html))
+            .or_else(|| {
+                self.content_text
+                    .map(|text| Text::new(TextKind::Text, text))
+            });
+        let authors = if self.authors.is_empty() {
+            self.author.into_iter().collect()
+        } else {
+            self.authors
+        };
+        let image = self.image.map(|url| Attachment {
+            url,
+            mime_type: None,
+            length: None,
+            duration: None,
+        });
+        Item {
+            title: self.title,
+            link: self.url.or(self.external_url),
+            id: self.id,
+            summary: self.summary.map(|text| Text::new(TextKind::Text, text)),
+            content,
+            published: parse_date(self.date_published),
+            updated: parse_date(self.date_modified),
+            attachments: self
+                .attachments
+                .into_iter()
+                .map(Attachment::from)
+                .chain(image)
+                .collect(),
+            authors: authors.into_iter().map(Person::from).collect(),
+            contributors: Vec::new(),
+            categories: self
+                .tags
+                .into_iter()
+                .map(|term| Category {
+                    term,
+                    label: None,
+                    scheme: None,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct FeedAttachment {
+    url: String,
+    mime_type: Option<String>,
+    // JSON numbers may be floats
+    size_in_bytes: Option<f64>,
+    duration_in_seconds: Option<f64>,
+}
+
+impl From<FeedAttachment> for Attachment {
+    fn from(attachment: FeedAttachment) -> Self {
+        Attachment {
+            url: attachment.url,
+            mime_type: attachment.mime_type,
+            length: attachment.size_in_bytes.map(|n| n as u64),
+            duration: attachment.duration_in_seconds.map(|n| n as u64),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Author {
+    #[serde(default)]
+    name: String,
+    url: Option<String>,
+}
+
+impl From<Author> for Person {
+    fn from(author: Author) -> Self {
+        Person {
+            name: author.name,
+            email: None,
+            uri: author.url,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::DateTime;
+
+    use super::*;
+
+    #[test]
+    fn json_feed_1_1() {
+        let s = include_bytes!("../../tests/data/json_1.1.json");
+        let r = parse(&s[..]).unwrap();
+        assert_eq!(
+            r,
+            Rss {
+                title: "json_1.1.title".into(),
+                format: Format::JsonFeed,
+                link: "http://example.com/blog".into(),
+                source: Some("http://example.com/blog/feed.json".into()),
+                hub: Some("http://example.com/hub".into()),
+                next_page: Some("http://example.com/blog/feed.json?page=2".into()),
+                description: Some("json_1.1.description".into()),
+                icon: Some("http://example.com/icon.png".into()),
+                language: Some("en-US".into()),
+                items: vec![
+                    Item {
+                        title: Some("json_1.1.items[0].title".into()),
+                        link: Some("http://example.com/blog/entry1".into()),
+                        id: Some("1".into()),
+                        summary: Some(Text::new(
+                            TextKind::Text,
+                            "json_1.1.items[0].summary".into()
+                        )),
+                        content: Some(Text::new(
+                            TextKind::Html,
+                            "<p>json_1.1.items[0].content_html</p>".into()
+                        )),
+                        published: Some(
+                            DateTime::parse_from_rfc3339("2000-01-01T01:00:00Z").unwrap()
+                        ),
+                        updated: Some(
+                            DateTime::parse_from_rfc3339("2000-01-01T00:00:00+00:00").unwrap()
+                        ),
+                        attachments: vec![Attachment {
+                            url: "http://example.com/blog/entry1.mp3".into(),
+                            mime_type: Some("audio/mpeg".into()),
+                            length: Some(1024),
+                            duration: Some(60),
+                        }],
+                        authors: vec![Person {
+                            name: "json_1.1.items[0].authors[0].name".into(),
+                            email: None,
+                            uri: Some("http://example.com".into()),
+                        }],
+                        contributors: vec![],
+                        categories: vec![Category {
+                            term: "json_1.1.items[0].tags[0]".into(),
+                            label: None,
+                            scheme: None,
+                        }],
+                    },
+                    Item {
+                        title: None,
+                        link: Some("http://example.com/external".into()),
+                        id: Some("json_1.1.items[1].id".into()),
+                        summary: None,
+                        content: Some(Text::new(
+                            TextKind::Text,
+                            "json_1.1.items[1].content_text".into()
+                        )),
+                        published: None,
+                        updated: None,
+                        attachments: vec![Attachment {
+                            url: "http://example.com/blog/entry2.png".into(),
+                            mime_type: None,
+                            length: None,
+                            duration: None,
+                        }],
+                        authors: vec![],
+                        contributors: vec![],
+                        categories: vec![],
+                    },
+                ],
+                ..Rss::default()
+            }
+        );
+    }
+
+    #[test]
+    fn json_feed_1_0() {
+        let s = include_bytes!("../../tests/data/json_1.0.json");
+        let r = parse(&s[..]).unwrap();
+        assert_eq!(r.title, "json_1.0.title");
+        assert_eq!(r.icon, Some("http://example.com/favicon.ico".into()));
+        assert_eq!(r.items[0].id, Some("json_1.0.items[0].id".into()));
+        assert_eq!(r.items[0].authors[0].name, "json_1.0.items[0].author.name");
+    }
+
+    fn parse_items(items: &str) -> Rss {
+        let input = format!(
+            r#"{{"version": "https://jsonfeed.org/version/1.1", "title": "title", "items": {}}}"#,
+            items
+        );
+        parse(input.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn item_content() {
+        let r = parse_items(
+            r#"[
+                {"id": "1", "summary": "summary", "content_html": "<p>html</p>", "content_text": "text"},
+                {"id": "2", "content_text": "text", "date_published": "2001-01-02T03:04:05Z"}
+            ]"#,
+        );
+        assert_eq!(
+            r.items[0].summary,
+            Some(Text::new(TextKind::Text, "summary".into()))
+        );
+        assert_eq!(
+            r.items[0].content,
+            Some(Text::new(TextKind::Html, "<p>html</p>".into()))
+        );
+        assert_eq!(r.items[1].summary, None);
+        assert_eq!(
+            r.items[1].published,
+            Some(DateTime::parse_from_rfc3339("2001-01-02T03:04:05Z").unwrap())
+        );
+        assert_eq!(
+            r.items[1].content,
+            Some(Text::new(TextKind::Text, "text".into()))
+        );
+    }
+
+    #[test]
+    fn invalid_dates() {
+        let r = parse_items(
+            r#"[{"id": "1", "date_published": "yesterday", "date_modified": "2001-01-02T03:04:05Z"}]"#,
+        );
+        assert_eq!(r.items[0].published, None);
+        assert!(r.items[0].updated.is_some());
+        assert_eq!(r.warnings, vec![Warning::InvalidDate("yesterday".into())]);
+    }
+
+    #[test]
+    fn attachments() {
+        let r = parse_items(
+            r#"[{
+                "id": "1",
+                "attachments": [
+                    {"url": "http://example.com/a.mp3", "mime_type": "audio/mpeg", "size_in_bytes": 1024, "duration_in_seconds": 60.5}
+                ]
+            }]"#,
+        );
+        assert_eq!(
+            r.items[0].attachments,
+            vec![Attachment {
+                url: "http://example.com/a.mp3".into(),
+                mime_type: Some("audio/mpeg".into()),
+                length: Some(1024),
+                duration: Some(60),
+            }]
+        );
+    }
+
+    #[test]
+    fn authors_and_tags() {
+        let r = parse_items(
+            r#"[
+                {"id": "1", "authors": [{"name": "a", "url": "http://example.com"}], "tags": ["t"]},
+                {"id": "2", "author": {"name": "b"}},
+                {"id": "3", "authors": [{"name": "c"}], "author": {"name": "d"}}
+            ]"#,
+        );
+        assert_eq!(
+            r.items[0].authors,
+            vec![Person {
+                name: "a".into(),
+                email: None,
+                uri: Some("http://example.com".into()),
+            }]
+        );
+        assert_eq!(r.items[0].categories[0].term, "t");
+        assert_eq!(r.items[1].authors[0].name, "b");
+        // `authors` of 1.1 replaces `author`
+        assert_eq!(r.items[2].authors.len(), 1);
+        assert_eq!(r.items[2].authors[0].name, "c");
+    }
+
+    #[test]
+    fn feed_metadata() {
+        let input = r#"{
+            "version": "https://jsonfeed.org/version/1",
+            "title": "title",
+            "description": "description",
+            "favicon": "favicon",
+            "language": "en",
+            "items": []
+        }"#;
+        let r = parse(input.as_bytes()).unwrap();
+        assert_eq!(r.description, Some("description".into()));
+        assert_eq!(r.icon, Some("favicon".into()));
+        assert_eq!(r.language, Some("en".into()));
+    }
+
+    #[test]
+    fn version() {
+        for version in [
+            "https://jsonfeed.org/version/1",
+            "https://jsonfeed.org/version/1.1",
+            "http://jsonfeed.org/version/1/",
+        ] {
+            let input = format!(r#"{{"version": "{}", "title": "", "items": []}}"#, version);
+            assert!(parse(input.as_bytes()).is_ok(), "{}", version);
+        }
+        for version in [
+            "https://jsonfeed.org/version/2",
+            "https://jsonfeed.org/version/10",
+        ] {
+            let input = format!(r#"{{"version": "{}", "title": "", "items": []}}"#, version);
+            assert!(
+                matches!(parse(input.as_bytes()), Err(Error::UnsupportedVersion(v)) if v == version)
+            );
+        }
+        assert!(matches!(
+            parse(&br#"{"title": "", "items": []}"#[..]),
+            Err(Error::Json(_))
+        ));
+    }
+}
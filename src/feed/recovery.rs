@@ -0,0 +1,275 @@
+//! Fixing common mistakes of malformed feeds before parsing.
+//!
+//! Stray `&` and unknown entities like `&foo;` are escaped as `&amp;`,
+//! so they become a part of the text. HTML named entities are already
+//! resolved by the unescaping of quick-xml. Stray `<` are escaped too.
+//!
+//! Open elements are tracked, so an end tag also closes the elements left
+//! open inside of it, e.g. `<description>a<br>b</description>`, and an end
+//! tag of an element that isn't open closes the current one, e.g.
+//! `<title>a</titel>`.
+
+use std::io::{self, BufRead, Read};
+
+/// Entities longer than this are treated as stray `&`
+const MAX_ENTITY_LEN: usize = 32;
+const CDATA_START: &[u8] = b"<![CDATA[";
+const COMMENT_START: &[u8] = b"<!--";
+
+/// Process the UTF-8 input line by line, entities, CDATA and comment
+/// markups we care about never span lines, tags may
+pub struct RecoveringReader<R> {
+    inner: R,
+    line: Vec<u8>,
+    output: Vec<u8>,
+    pos: usize,
+    in_cdata: bool,
+    in_comment: bool,
+    /// The tag being read, it's written out once it's complete
+    tag: Option<Vec<u8>>,
+    /// Quote of the attribute value being read in `tag`
+    quote: Option<u8>,
+    /// Names of open elements
+    open: Vec<Vec<u8>>,
+    eof: bool,
+}
+
+impl<R: BufRead> RecoveringReader<R> {
+    pub fn new(inner: R) -> Self {
+        RecoveringReader {
+            inner,
+            line: Vec::new(),
+            output: Vec::new(),
+            pos: 0,
+            in_cdata: false,
+            in_comment: false,
+            tag: None,
+            quote: None,
+            open: Vec::new(),
+            eof: false,
+        }
+    }
+
+    fn fix_line(&mut self) {
+        let line = std::mem::take(&mut self.line);
+        let mut i = 0;
+        while i < line.len() {
+            let rest = &line[i..];
+            if self.in_cdata || self.in_comment {
+                // Copy the content of CDATA or comment as is
+                let closing: &[u8] = if self.in_cdata { b"]]>" } else { b"-->" };
+                let n = match find(rest, closing) {
+                    Some(n) => {
+                        self.in_cdata = false;
+                        self.in_comment = false;
+                        n + closing.len()
+                    }
+                    None => rest.len(),
+                };
+                self.output.extend_from_slice(&rest[..n]);
+                i += n;
+                continue;
+            }
+            let byte = rest[0];
+            if let Some(tag) = &mut self.tag {
+                match self.quote {
+                    Some(quote) if byte == quote => self.quote = None,
+                    Some(_) => (),
+                    None if byte == b'"' || byte == b'\'' => self.quote = Some(byte),
+                    None if byte == b'>' => {
+                        tag.push(byte);
+                        let tag = self.tag.take().unwrap();
+                        self.close_tag(tag);
+                        i += 1;
+                        continue;
+                    }
+                    None => (),
+                }
+                push_escaped(tag, rest);
+            } else if rest.starts_with(CDATA_START) {
+                self.in_cdata = true;
+                self.output.extend_from_slice(CDATA_START);
+                i += CDATA_START.len();
+                continue;
+            } else if rest.starts_with(COMMENT_START) {
+                self.in_comment = true;
+                self.output.extend_from_slice(COMMENT_START);
+                i += COMMENT_START.len();
+                continue;
+            } else if byte == b'<' {
+                match rest.get(1) {
+                    Some(b) if b.is_ascii_alphabetic() || b"_:/?!".contains(b) => {
+                        self.tag = Some(vec![byte]);
+                    }
+                    // Not markup, e.g. `a < b`
+                    _ => self.output.extend_from_slice(b"&lt;"),
+                }
+            } else {
+                push_escaped(&mut self.output, rest);
+            }
+            i += 1;
+        }
+        self.line = line;
+    }
+
+    /// `tag` is complete, from `<` to `>`
+    fn close_tag(&mut self, tag: Vec<u8>) {
+        if tag.starts_with(b"<?") || tag.starts_with(b"<!") || tag.ends_with(b"/>") {
+            self.output.extend_from_slice(&tag);
+        } else if let Some(end) = tag.strip_prefix(b"</") {
+            let name = tag_name(end);
+            match self.open.iter().rposition(|open| open == name) {
+                Some(i) => {
+                    for unclosed in self.open.drain(i + 1..).rev() {
+                        self.output.extend_from_slice(b"</");
+                        self.output.extend_from_slice(&unclosed);
+                        self.output.push(b'>');
+                    }
+                    self.open.pop();
+                    self.output.extend_from_slice(&tag);
+                }
+                // A misspelled end tag, e.g. `<title>a</titel>`
+                None => {
+                    if let Some(unclosed) = self.open.pop() {
+                        self.output.extend_from_slice(b"</");
+                        self.output.extend_from_slice(&unclosed);
+                        self.output.push(b'>');
+                    }
+                }
+            }
+        } else {
+            self.open.push(tag_name(&tag[1..]).to_vec());
+            self.output.extend_from_slice(&tag);
+        }
+    }
+}
+
+/// The name at the beginning of `s`
+fn tag_name(s: &[u8]) -> &[u8] {
+    let end = s
+        .iter()
+        .position(|&b| b.is_ascii_whitespace() || b == b'>' || b == b'/')
+        .unwrap_or(s.len());
+    &s[..end]
+}
+
+/// Push the first byte of `s`, escape it if it's a stray `&`
+fn push_escaped(output: &mut Vec<u8>, s: &[u8]) {
+    if s[0] == b'&' && !is_valid_entity(s) {
+        output.extend_from_slice(b"&amp;");
+    } else {
+        output.push(s[0]);
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// `s` starts with `&`
+fn is_valid_entity(s: &[u8]) -> bool {
+    let end = match s.iter().take(MAX_ENTITY_LEN).position(|&b| b == b';') {
+        Some(end) => end,
+        None => return false,
+    };
+    let entity = &s[1..end];
+    if entity.is_empty()
+        || !entity
+            .iter()
+            .all(|b| b.is_ascii_alphanumeric() || *b == b'#')
+    {
+        return false;
+    }
+    // Only ASCII here
+    let entity = std::str::from_utf8(&s[..=end]).unwrap();
+    quick_xml::escape::unescape(entity).is_ok()
+}
+
+impl<R: BufRead> Read for RecoveringReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for RecoveringReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        // A line may only be a part of a tag, there is no output yet
+        while self.pos == self.output.len() && !self.eof {
+            self.line.clear();
+            self.output.clear();
+            self.pos = 0;
+            if self.inner.read_until(b'\n', &mut self.line)? == 0 {
+                self.eof = true;
+                // Truncated, leave it to the parser
+                if let Some(tag) = self.tag.take() {
+                    self.output = tag;
+                }
+            } else {
+                self.fix_line();
+            }
+        }
+        Ok(&self.output[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.output.len());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn recover(input: &str) -> String {
+        let mut output = String::new();
+        RecoveringReader::new(input.as_bytes())
+            .read_to_string(&mut output)
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn ampersands() {
+        assert_eq!(recover("a & b &amp; c"), "a &amp; b &amp; c");
+        assert_eq!(
+            recover("&nbsp;&mdash;&#160;&#xA0;"),
+            "&nbsp;&mdash;&#160;&#xA0;"
+        );
+        assert_eq!(recover("&foo; &#xZZ; &;"), "&amp;foo; &amp;#xZZ; &amp;;");
+        assert_eq!(recover("?a=1&b=2;"), "?a=1&amp;b=2;");
+        assert_eq!(
+            recover(r#"<a href="?a=1&b=2">"#),
+            r#"<a href="?a=1&amp;b=2">"#
+        );
+    }
+
+    #[test]
+    fn cdata_and_comments() {
+        let input = "<![CDATA[a & b\n&c]]> & <!-- & -->\n<!--\n&\n--> &";
+        let output = "<![CDATA[a & b\n&c]]> &amp; <!-- & -->\n<!--\n&\n--> &amp;";
+        assert_eq!(recover(input), output);
+    }
+
+    #[test]
+    fn tags() {
+        assert_eq!(
+            recover("<item><title>a</item>"),
+            "<item><title>a</title></item>"
+        );
+        assert_eq!(
+            recover("<description>a<br>b</description>"),
+            "<description>a<br>b</br></description>"
+        );
+        assert_eq!(recover("<title>a</titel>"), "<title>a</title>");
+        assert_eq!(recover("<a>1 < 2</a>"), "<a>1 &lt; 2</a>");
+        // Tags spanning lines, `>` in attribute values
+        let input = "<?xml version=\"1.0\"?>\n<rss version=\"2.0\"\n a='>'><br/><x:y></x:y></rss>";
+        assert_eq!(recover(input), input);
+    }
+}
@@ -1,11 +1,13 @@
 use std::cmp;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc,
 };
+use std::time::SystemTime;
 
 use futures::{future::FutureExt, select_biased};
+use reqwest::StatusCode;
 use teloxide::requests::Requester;
 use teloxide::types::ChatId;
 use teloxide::{ApiError, Bot, RequestError};
@@ -16,12 +18,25 @@ use tokio::{
 };
 use tokio_stream::StreamExt;
 use tokio_util::time::DelayQueue;
+use url::Url;
 
-use crate::client::pull_feed;
+use crate::client::{
+    is_local, pull_feed, pull_feed_if_modified, same_origin, FeedError, Freshness, Pulled,
+    RequestOptions, Retry, Validators,
+};
 use crate::data::{Database, Feed, FeedUpdate};
+use crate::feed::Rss;
 use crate::messages::{format_large_msg, Escape};
+use crate::websub;
 
-pub fn start(bot: Bot, db: Arc<Mutex<Database>>, min_interval: u32, max_interval: u32) {
+pub fn start(
+    bot: Bot,
+    db: Arc<Mutex<Database>>,
+    min_interval: u32,
+    max_interval: u32,
+    max_archive_pages: u32,
+    follow_self_links: bool,
+) {
     let mut queue = FetchQueue::new();
     // TODO: Don't use interval, it can accumulate ticks
     // replace it with delay_until
@@ -37,7 +52,7 @@ pub fn start(bot: Bot, db: Arc<Mutex<Database>>, min_interval: u32, max_interval
                     let opportunity = throttle.acquire();
                     tokio::spawn(async move {
                         opportunity.wait().await;
-                        if let Err(e) = fetch_and_push_updates(bot, db, feed).await {
+                        if let Err(e) = fetch_and_push_updates(bot, db, feed, max_interval, max_archive_pages, follow_self_links).await {
                             // crate::print_error(e);
                             eprintln!("Error: {}", e);
                             e.chain().skip(1).for_each(|cause| eprintln!("caused by: {}", cause));
@@ -47,14 +62,10 @@ pub fn start(bot: Bot, db: Arc<Mutex<Database>>, min_interval: u32, max_interval
                 _ = interval.tick().fuse() => {
                     let feeds = db.lock().await.all_feeds();
                     for feed in feeds {
-                        let feed_interval = cmp::min(
-                            cmp::max(
-                                feed.ttl.map(|ttl| ttl * 60).unwrap_or_default(),
-                                min_interval,
-                            ),
-                            max_interval,
-                        ) as u64 - 1; // after -1, we can stagger with `interval`
-                        queue.enqueue(feed, Duration::from_secs(feed_interval));
+                        let (feed_interval, _) = schedule(&feed, min_interval, max_interval);
+                        // after -1, we can stagger with `interval`
+                        let feed_interval = feed_interval.saturating_sub(Duration::from_secs(1));
+                        queue.enqueue(feed, feed_interval);
                     }
                 }
             }
@@ -62,22 +73,167 @@ pub fn start(bot: Bot, db: Arc<Mutex<Database>>, min_interval: u32, max_interval
     });
 }
 
+/// What decided the fetch interval of a feed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleReason {
+    MinInterval,
+    MaxInterval,
+    Ttl,
+    MaxAge,
+    Expires,
+    RetryAfter,
+    /// The last fetch failed with an error that won't go away soon
+    Backoff,
+    /// Updates are pushed by the hub, poll only in case pushes get lost
+    WebSub,
+}
+
+impl std::fmt::Display for ScheduleReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ScheduleReason::MinInterval => "min interval",
+            ScheduleReason::MaxInterval => "max interval",
+            ScheduleReason::Ttl => "ttl",
+            ScheduleReason::MaxAge => "Cache-Control max-age",
+            ScheduleReason::Expires => "Expires",
+            ScheduleReason::RetryAfter => "Retry-After",
+            ScheduleReason::Backoff => "failing",
+            ScheduleReason::WebSub => "WebSub",
+        })
+    }
+}
+
+/// The fetch interval of a feed, the longest of `ttl` and the freshness
+/// of the last response, clamped between `min_interval` and `max_interval`.
+/// `Retry-After` of the server can only make it longer.
+/// Feeds failing with errors that won't go away soon are fetched at `max_interval`.
+pub fn schedule(feed: &Feed, min_interval: u32, max_interval: u32) -> (Duration, ScheduleReason) {
+    let min = Duration::from_secs(min_interval as u64);
+    let max = Duration::from_secs(max_interval as u64);
+    let (interval, reason) = if feed.backoff {
+        (max, ScheduleReason::Backoff)
+    } else if websub::lease_active(feed) {
+        (max, ScheduleReason::WebSub)
+    } else {
+        let ttl = feed
+            .ttl
+            .map(|ttl| (Duration::from_secs(ttl as u64 * 60), ScheduleReason::Ttl));
+        let freshness = feed.freshness.map(|freshness| match freshness {
+            Freshness::MaxAge(age) => (age, ScheduleReason::MaxAge),
+            Freshness::Expires(age) => (age, ScheduleReason::Expires),
+        });
+        match ttl.into_iter().chain(freshness).max_by_key(|(d, _)| *d) {
+            Some((d, _)) if d < min => (min, ScheduleReason::MinInterval),
+            Some((d, _)) if d > max => (max, ScheduleReason::MaxInterval),
+            Some(hint) => hint,
+            None => (min, ScheduleReason::MinInterval),
+        }
+    };
+    let retry_after = feed
+        .retry_after
+        .and_then(|t| t.duration_since(SystemTime::now()).ok());
+    match retry_after {
+        Some(delay) if delay > interval => (cmp::min(delay, max), ScheduleReason::RetryAfter),
+        _ => (interval, reason),
+    }
+}
+
 async fn fetch_and_push_updates(
     bot: Bot,
     db: Arc<Mutex<Database>>,
     feed: Feed,
+    max_interval: u32,
+    max_archive_pages: u32,
+    follow_self_links: bool,
 ) -> Result<(), anyhow::Error> {
-    let new_feed = match pull_feed(&feed.link).await {
-        Ok(feed) => feed,
+    let validators = Validators {
+        etag: feed.etag.clone(),
+        last_modified: feed.last_modified.clone(),
+    };
+    let result = pull_feed_if_modified(&feed.link, &feed.request, &validators).await;
+    let (mut feed, result) = match result {
+        Ok(Pulled::Moved(new_link, pulled)) => {
+            let migrated = db.lock().await.migrate_feed(feed.id(), &new_link);
+            match migrated {
+                Some(migrated) => {
+                    let msg = tr!(
+                        "feed_moved",
+                        link = Escape(&feed.link),
+                        title = Escape(&feed.title),
+                        new_link = Escape(&new_link)
+                    );
+                    push_updates(
+                        &bot,
+                        &db,
+                        feed.subscribers,
+                        &msg,
+                        Some(teloxide::types::ParseMode::Html),
+                    )
+                    .await?;
+                    (migrated, Ok(*pulled))
+                }
+                // user unsubscribed while fetching the feed
+                None => return Ok(()),
+            }
+        }
+        result => (feed, result),
+    };
+    let mut new_feed = match result {
+        Ok(Pulled::Modified(new_feed, validators, freshness)) => {
+            let mut db = db.lock().await;
+            db.set_validators(feed.id(), validators);
+            db.set_schedule_hints(feed.id(), freshness, None);
+            db.set_backoff(feed.id(), false);
+            *new_feed
+        }
+        Ok(Pulled::NotModified(freshness)) => {
+            let mut db = db.lock().await;
+            db.reset_down_time(feed.id());
+            db.set_schedule_hints(feed.id(), freshness, None);
+            db.set_backoff(feed.id(), false);
+            return Ok(());
+        }
+        Ok(Pulled::Moved(..)) => unreachable!("redirects are followed only once"),
+        Err(FeedError::Status(StatusCode::GONE)) => {
+            let deleted = db.lock().await.delete_feed(feed.id());
+            if let Some(deleted) = deleted {
+                if let Some(hub) = deleted.hub {
+                    tokio::spawn(websub::unsubscribe(hub));
+                }
+                let msg = tr!(
+                    "feed_gone",
+                    link = Escape(&feed.link),
+                    title = Escape(&feed.title)
+                );
+                push_updates(
+                    &bot,
+                    &db,
+                    deleted.subscribers,
+                    &msg,
+                    Some(teloxide::types::ParseMode::Html),
+                )
+                .await?;
+            }
+            return Ok(());
+        }
         Err(e) => {
-            let down_time = db.lock().await.get_or_update_down_time(&feed.link);
+            db.lock()
+                .await
+                .set_backoff(feed.id(), e.retry() == Retry::Backoff);
+            if let FeedError::RetryAfter(_, delay) = e {
+                let retry_after = SystemTime::now() + delay;
+                db.lock()
+                    .await
+                    .set_schedule_hints(feed.id(), feed.freshness, Some(retry_after));
+            }
+            let down_time = db.lock().await.get_or_update_down_time(feed.id());
             if down_time.is_none() {
                 // user unsubscribed while fetching the feed
                 return Ok(());
             }
             // 5 days
             if down_time.unwrap().as_secs() > 5 * 24 * 60 * 60 {
-                db.lock().await.reset_down_time(&feed.link);
+                db.lock().await.reset_down_time(feed.id());
                 let msg = tr!(
                     "continuous_fetch_error",
                     link = Escape(&feed.link),
@@ -97,7 +253,179 @@ async fn fetch_and_push_updates(
         }
     };
 
-    let updates = db.lock().await.update(&feed.link, new_feed);
+    if let Some(canonical) = canonical_link(&feed.link, &new_feed) {
+        // Only once for each canonical URL, there's no point nagging
+        let verified = if feed.self_link.as_deref() != Some(&*canonical)
+            && self_link_check_due(&feed, max_interval)
+        {
+            let verified = is_same_feed(&feed, &canonical).await;
+            if !verified {
+                db.lock()
+                    .await
+                    .set_self_link_checked(feed.id(), Some(SystemTime::now()));
+            }
+            verified
+        } else {
+            false
+        };
+        if verified {
+            db.lock()
+                .await
+                .set_self_link(feed.id(), Some(canonical.clone()));
+            let subscribers = feed.subscribers.clone();
+            let migrated = if follow_self_links {
+                let migrated = db.lock().await.migrate_feed(feed.id(), &canonical);
+                if migrated.is_none() {
+                    // user unsubscribed while fetching the feed
+                    return Ok(());
+                }
+                migrated
+            } else {
+                None
+            };
+            let msg = match migrated {
+                Some(migrated) => {
+                    let msg = tr!(
+                        "feed_self_link_followed",
+                        link = Escape(&feed.link),
+                        title = Escape(&feed.title),
+                        new_link = Escape(&canonical)
+                    );
+                    feed = migrated;
+                    msg
+                }
+                None => tr!(
+                    "feed_self_link_differs",
+                    link = Escape(&feed.link),
+                    title = Escape(&feed.title),
+                    new_link = Escape(&canonical)
+                ),
+            };
+            push_updates(
+                &bot,
+                &db,
+                subscribers,
+                &msg,
+                Some(teloxide::types::ParseMode::Html),
+            )
+            .await?;
+        }
+    }
+
+    if websub::enabled() {
+        if let Some(hub_url) = new_feed.hub.clone() {
+            let topic = new_feed.source.clone().unwrap_or_else(|| feed.link.clone());
+            let db = db.clone();
+            let (feed_id, link) = (feed.id(), feed.link.clone());
+            tokio::spawn(async move {
+                if let Err(e) = websub::subscribe_if_needed(db, feed_id, &hub_url, &topic).await {
+                    eprintln!(
+                        "Warning: failed to subscribe {} to hub {}: {}",
+                        link, hub_url, e
+                    );
+                }
+            });
+        }
+    }
+
+    if max_archive_pages > 0 {
+        catch_up(&db, &feed, &mut new_feed, max_archive_pages).await;
+    }
+    push_feed_updates(bot, db, feed, new_feed).await
+}
+
+/// The URL a feed claims to be at by its self link, if it's not `link`
+fn canonical_link(link: &str, rss: &Rss) -> Option<String> {
+    let link = Url::parse(link).ok()?;
+    let canonical = link.join(rss.source.as_deref()?.trim()).ok()?;
+    if !matches!(canonical.scheme(), "http" | "https") || canonical == link {
+        return None;
+    }
+    Some(canonical.into())
+}
+
+/// Unverified self links are checked again at `max_interval`, the failure
+/// may be temporary, but most of them are mistakes
+fn self_link_check_due(feed: &Feed, max_interval: u32) -> bool {
+    feed.self_link_checked.is_none_or(|checked| {
+        checked
+            .elapsed()
+            .map_or(true, |elapsed| elapsed.as_secs() >= max_interval as u64)
+    })
+}
+
+/// A self link can be anything, mistakes are common. Only trust it if
+/// there is a feed claiming the same self link
+async fn is_same_feed(feed: &Feed, canonical: &str) -> bool {
+    // Credentials are only for the site they are given to
+    if !feed.request.is_empty() && !same_origin(&feed.link, canonical) {
+        return false;
+    }
+    // A self link permanently redirected elsewhere, e.g. from http to https,
+    // would be followed back and forth on every fetch
+    match pull_feed_if_modified(canonical, &feed.request, &Validators::default()).await {
+        Ok(Pulled::Modified(rss, ..)) => {
+            rss.source.is_some() && canonical_link(canonical, &rss).is_none()
+        }
+        _ => false,
+    }
+}
+
+/// Walk older pages of a paged or archived feed (RFC 5005) until one of them
+/// contains seen items, so the items missed since the last fetch are delivered
+async fn catch_up(db: &Arc<Mutex<Database>>, feed: &Feed, new_feed: &mut Rss, max_pages: u32) {
+    let link = &feed.link;
+    let older_page = |page: &Rss| page.next_page.clone().or_else(|| page.prev_archive.clone());
+    let mut visited = HashSet::new();
+    visited.insert(link.to_owned());
+    let mut seen = db.lock().await.has_seen_any(feed.id(), &new_feed.items);
+    let mut next = older_page(new_feed);
+    let mut pages = 0;
+    while let (false, Some(url)) = (seen, next) {
+        // Some feeds link back to pages already walked
+        if pages == max_pages || !visited.insert(url.clone()) {
+            break;
+        }
+        // Local feeds are only for bot admins, remote feeds can't link to them
+        if is_local(&url) && !is_local(link) {
+            break;
+        }
+        pages += 1;
+        // Credentials are only for the site they are given to
+        let request = if same_origin(link, &url) {
+            feed.request.clone()
+        } else {
+            RequestOptions::default()
+        };
+        let page = match pull_feed(&url, &request).await {
+            Ok(page) => page,
+            Err(e) => {
+                eprintln!(
+                    "Warning: failed to fetch older page {} of {}: {}",
+                    url, link, e
+                );
+                break;
+            }
+        };
+        seen = db.lock().await.has_seen_any(feed.id(), &page.items);
+        next = older_page(&page);
+        for item in page.items {
+            // Items move to the next page while walking
+            if !new_feed.items.contains(&item) {
+                new_feed.items.push(item);
+            }
+        }
+    }
+}
+
+/// Push updates of a polled or WebSub pushed feed to subscribers
+pub async fn push_feed_updates(
+    bot: Bot,
+    db: Arc<Mutex<Database>>,
+    feed: Feed,
+    new_feed: Rss,
+) -> Result<(), anyhow::Error> {
+    let updates = db.lock().await.update(feed.id(), new_feed);
     for update in updates {
         match update {
             FeedUpdate::Items(items) => {
@@ -269,3 +597,113 @@ impl Drop for Opportunity {
         self.counter.fetch_sub(1, Ordering::SeqCst);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn schedules() {
+        let secs = Duration::from_secs;
+        let mut feed = Feed::default();
+        assert_eq!(
+            schedule(&feed, 300, 3600),
+            (secs(300), ScheduleReason::MinInterval)
+        );
+        feed.ttl = Some(10);
+        assert_eq!(schedule(&feed, 300, 3600), (secs(600), ScheduleReason::Ttl));
+        feed.freshness = Some(Freshness::MaxAge(secs(1200)));
+        assert_eq!(
+            schedule(&feed, 300, 3600),
+            (secs(1200), ScheduleReason::MaxAge)
+        );
+        feed.freshness = Some(Freshness::Expires(secs(7200)));
+        assert_eq!(
+            schedule(&feed, 300, 3600),
+            (secs(3600), ScheduleReason::MaxInterval)
+        );
+        feed.freshness = Some(Freshness::Expires(secs(0)));
+        feed.retry_after = Some(SystemTime::now() + secs(1800));
+        let (interval, reason) = schedule(&feed, 300, 3600);
+        assert!(interval > secs(1700) && interval <= secs(1800));
+        assert_eq!(reason, ScheduleReason::RetryAfter);
+        feed.backoff = true;
+        assert_eq!(
+            schedule(&feed, 300, 3600),
+            (secs(3600), ScheduleReason::Backoff)
+        );
+    }
+
+    #[test]
+    fn self_link_checks() {
+        let mut feed = Feed::default();
+        assert!(self_link_check_due(&feed, 3600));
+        feed.self_link_checked = Some(SystemTime::now() - Duration::from_secs(600));
+        assert!(!self_link_check_due(&feed, 3600));
+        assert!(self_link_check_due(&feed, 300));
+    }
+
+    #[tokio::test]
+    async fn self_links() {
+        use hyper::header::LOCATION;
+        use hyper::service::{make_service_fn, service_fn};
+        use hyper::{Body, Request, Response, Server};
+
+        crate::client::init_test_client();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = Server::from_tcp(listener)
+            .unwrap()
+            .serve(make_service_fn(|_| async {
+                Ok::<_, std::convert::Infallible>(service_fn(|req: Request<Body>| async move {
+                    let feed = |self_link| {
+                        Response::builder().body(Body::from(format!(
+                            r#"<feed xmlns="http://www.w3.org/2005/Atom"><title>t</title><link rel="self" href="{}"/></feed>"#,
+                            self_link
+                        )))
+                    };
+                    let resp = match req.uri().path() {
+                        "/moved" => Response::builder()
+                            .status(StatusCode::MOVED_PERMANENTLY)
+                            .header(LOCATION, "/feed")
+                            .body(Body::empty()),
+                        "/alias" => feed("/alias"),
+                        _ => feed("/moved"),
+                    };
+                    Ok::<_, std::convert::Infallible>(resp.unwrap())
+                }))
+            }));
+        tokio::spawn(server);
+
+        let mut feed = Feed::default();
+        feed.link = format!("{}/feed", base);
+        assert!(is_same_feed(&feed, &format!("{}/alias", base)).await);
+        // Redirected back to the feed
+        assert!(!is_same_feed(&feed, &format!("{}/moved", base)).await);
+    }
+
+    #[test]
+    fn canonical_links() {
+        let rss = |source: Option<&str>| Rss {
+            source: source.map(str::to_owned),
+            ..Default::default()
+        };
+        let link = "http://example.com/feed.xml";
+        assert_eq!(canonical_link(link, &rss(None)), None);
+        assert_eq!(canonical_link(link, &rss(Some(link))), None);
+        assert_eq!(
+            canonical_link(link, &rss(Some("HTTP://EXAMPLE.COM:80/feed.xml"))),
+            None
+        );
+        assert_eq!(
+            canonical_link(link, &rss(Some("https://example.com/feed.xml"))),
+            Some("https://example.com/feed.xml".into())
+        );
+        assert_eq!(
+            canonical_link(link, &rss(Some("/atom.xml"))),
+            Some("http://example.com/atom.xml".into())
+        );
+        assert_eq!(canonical_link(link, &rss(Some("urn:feed:1"))), None);
+    }
+}
@@ -2,6 +2,7 @@
 #![recursion_limit = "256"]
 
 use std::env;
+use std::net::{SocketAddr, TcpListener};
 use std::panic;
 use std::path::PathBuf;
 use std::process;
@@ -13,6 +14,7 @@ use once_cell::sync::OnceCell;
 
 use teloxide::types::UserId;
 use tokio::{self, sync::Mutex};
+use url::Url;
 
 // Include the tr! macro and localizations
 include!(concat!(env!("OUT_DIR"), "/ctl10n_macros.rs"));
@@ -25,6 +27,7 @@ mod fetcher;
 mod gardener;
 mod messages;
 mod opml;
+mod websub;
 
 use crate::data::Database;
 
@@ -81,6 +84,76 @@ pub struct Opt {
     /// Make bot commands only accessible for group admins.
     #[arg(long)]
     restricted: bool,
+    /// Maximum number of older pages of paged or archived feeds (RFC 5005)
+    /// to fetch when items may have been missed, 0 is disabled
+    #[arg(long, value_name = "pages", default_value = "0")]
+    max_archive_pages: u32,
+    /// Move subscriptions of a feed to the URL given by its self link,
+    /// instead of only suggesting it to subscribers
+    #[arg(long)]
+    follow_self_links: bool,
+    /// Maximum number of feed requests at the same time, 0 is unlimited
+    #[arg(long, value_name = "number", default_value = "64")]
+    max_fetches: usize,
+    /// Maximum number of feed requests at the same time to one host, 0 is unlimited
+    #[arg(long, value_name = "number", default_value = "4")]
+    max_host_fetches: usize,
+    /// Maximum number of requests per minute to one host, 0 is unlimited
+    #[arg(long, value_name = "number", default_value = "60")]
+    max_host_requests: u32,
+    /// Public URL of the WebSub callback endpoint, enables WebSub push subscriptions
+    #[arg(long, value_name = "url")]
+    websub_callback: Option<String>,
+    /// Listening address of the WebSub callback endpoint
+    #[arg(long, value_name = "address", default_value = "0.0.0.0:8080")]
+    websub_listen: SocketAddr,
+    /// Timeout of connecting to feed servers
+    #[arg(long, value_name = "seconds", default_value = "10")]
+    connect_timeout: u64,
+    /// Timeout of waiting for a response or more data from feed servers
+    #[arg(long, value_name = "seconds", default_value = "10")]
+    read_timeout: u64,
+    /// Timeout of a whole feed request, from sending it to reading all of the body
+    #[arg(long, value_name = "seconds", default_value = "60")]
+    request_timeout: u64,
+    /// Maximum number of redirects to follow when fetching feeds
+    #[arg(long, value_name = "number", default_value = "5")]
+    max_redirects: usize,
+    /// User-Agent of feed requests [default: rssbot/<version> (+https://t.me/<bot name>)]
+    #[arg(long, value_name = "user agent", value_parser(parse_user_agent))]
+    user_agent: Option<String>,
+    /// Proxy for fetching feeds, http, https, socks5 and socks5h proxies are supported.
+    /// Overrides proxies of environment variables
+    #[arg(long, value_name = "url", value_parser(parse_proxy))]
+    proxy: Option<Url>,
+    /// Proxy for fetching http:// feeds, takes precedence over --proxy
+    #[arg(long, value_name = "url", value_parser(parse_proxy))]
+    http_proxy: Option<Url>,
+    /// Proxy for fetching https:// feeds, takes precedence over --proxy
+    #[arg(long, value_name = "url", value_parser(parse_proxy))]
+    https_proxy: Option<Url>,
+    /// Hosts, domains or IP ranges to fetch without the proxies above.
+    /// This argument can be passed multiple times or separated by commas
+    #[arg(long, value_name = "host", value_delimiter = ',')]
+    no_proxy: Vec<String>,
+    /// Directory of local feeds, `file://` feeds must be in one of them.
+    /// Only bot admins can subscribe to `file://` and `exec:` feeds.
+    /// This argument can be passed multiple times
+    #[arg(long, value_name = "path", number_of_values = 1)]
+    local_feed_dir: Vec<PathBuf>,
+    /// Command of the `exec:<name>` feed, its output is parsed as a feed.
+    /// The command is split by whitespace, not run by a shell.
+    /// This argument can be passed multiple times
+    #[arg(
+        long,
+        value_name = "name=command",
+        number_of_values = 1,
+        value_parser(parse_exec_feed)
+    )]
+    exec_feed: Vec<(String, Vec<String>)>,
+    /// Timeout of `exec:` feed commands
+    #[arg(long, value_name = "seconds", default_value = "30")]
+    exec_timeout: u64,
     /// DANGER: Insecure mode, accept invalid TLS certificates
     #[arg(long)]
     insecure: bool,
@@ -96,6 +169,33 @@ fn parse_check_interval(s: &str) -> Result<u32, String> {
     })
 }
 
+fn parse_user_agent(s: &str) -> Result<String, String> {
+    reqwest::header::HeaderValue::from_str(s)
+        .map(|_| s.to_owned())
+        .map_err(|e| e.to_string())
+}
+
+fn parse_exec_feed(s: &str) -> Result<(String, Vec<String>), String> {
+    let (name, command) = s.split_once('=').ok_or("expected <name>=<command>")?;
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err("invalid name".into());
+    }
+    let command: Vec<String> = command.split_whitespace().map(str::to_owned).collect();
+    if command.is_empty() {
+        return Err("empty command".into());
+    }
+    Ok((name.to_owned(), command))
+}
+
+fn parse_proxy(s: &str) -> Result<Url, String> {
+    let url = Url::parse(s).map_err(|e| e.to_string())?;
+    match url.scheme() {
+        "http" | "https" | "socks5" | "socks5h" => Ok(url),
+        scheme => Err(format!("unsupported proxy scheme: {}", scheme)),
+    }
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     enable_fail_fast();
@@ -117,13 +217,31 @@ async fn main() -> anyhow::Result<()> {
 
     let bot_name = me.user.username.clone().context("Bot name is not set")?;
     let bot_id = me.user.id;
-    crate::client::init_client(&bot_name, opt.insecure, opt.max_feed_size);
+    crate::client::init_client(&bot_name, &opt).context("Failed to initialize HTTP client")?;
+    crate::client::init_local_sources(&opt);
 
     BOT_NAME.set(bot_name).unwrap();
     BOT_ID.set(bot_id).unwrap();
 
     gardener::start_pruning(bot.clone(), db.clone());
-    fetcher::start(bot.clone(), db.clone(), opt.min_interval, opt.max_interval);
+    fetcher::start(
+        bot.clone(),
+        db.clone(),
+        opt.min_interval,
+        opt.max_interval,
+        opt.max_archive_pages,
+        opt.follow_self_links,
+    );
+    if let Some(callback) = &opt.websub_callback {
+        let listener = TcpListener::bind(opt.websub_listen)
+            .context("Failed to listen on the WebSub callback address")?;
+        websub::init(callback);
+        let (bot, push_db) = (bot.clone(), db.clone());
+        websub::start(listener, db.clone(), move |feed, rss| {
+            fetcher::push_feed_updates(bot.clone(), push_db.clone(), feed, rss)
+        })
+        .context("Failed to start the WebSub callback server")?;
+    }
 
     let opt = Arc::new(opt);
 
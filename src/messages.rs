@@ -1,4 +1,5 @@
 use std::fmt;
+use std::time::Duration;
 
 pub const TELEGRAM_MAX_MSG_LEN: usize = 4096;
 
@@ -20,6 +21,30 @@ where
     msgs
 }
 
+/// Truncate to `max_chars` characters, with an ellipsis if truncated
+pub fn truncate(s: &str, max_chars: usize) -> String {
+    match s.char_indices().nth(max_chars) {
+        Some((i, _)) => format!("{}…", &s[..i]),
+        None => s.to_owned(),
+    }
+}
+
+/// `1h30m`, `5m`, `42s`
+pub fn format_duration(d: Duration) -> String {
+    let secs = d.as_secs();
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    let mut output = String::new();
+    for (n, unit) in [(h, "h"), (m, "m"), (s, "s")] {
+        if n > 0 {
+            output.push_str(&format!("{}{}", n, unit));
+        }
+    }
+    if output.is_empty() {
+        output.push_str("0s");
+    }
+    output
+}
+
 pub struct Escape<'a>(pub &'a str);
 
 impl<'a> fmt::Display for Escape<'a> {
@@ -43,6 +43,13 @@ pub fn into_opml(feeds: Vec<Feed>) -> String {
                     outline.push_attribute(Attribute::from(("type", "rss")));
                     outline.push_attribute(Attribute::from(("text", feed.title.as_str())));
                     outline.push_attribute(Attribute::from(("xmlUrl", feed.link.as_str())));
+                    if let Some(description) = &feed.description {
+                        outline
+                            .push_attribute(Attribute::from(("description", description.as_str())));
+                    }
+                    if let Some(language) = &feed.language {
+                        outline.push_attribute(Attribute::from(("language", language.as_str())));
+                    }
                     writer.write_event(Event::Empty(outline))?;
                 }
                 Ok(())
@@ -84,6 +91,8 @@ fn test_to_opml() {
     let mut feed2 = Feed::default();
     feed2.title = "title2".into();
     feed2.link = "link2".into();
+    feed2.description = Some("description2".into());
+    feed2.language = Some("en".into());
     let feeds = vec![feed1, feed2];
     let r = format!(
         "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
@@ -95,7 +104,8 @@ fn test_to_opml() {
          </head>\
          <body>\
          <outline type=\"rss\" text=\"title1\" xmlUrl=\"link1\"/>\
-         <outline type=\"rss\" text=\"title2\" xmlUrl=\"link2\"/>\
+         <outline type=\"rss\" text=\"title2\" xmlUrl=\"link2\" \
+         description=\"description2\" language=\"en\"/>\
          </body>\
          </opml>",
         Local::now().format("%a, %d %b %Y %T %Z")
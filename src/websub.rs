@@ -0,0 +1,558 @@
+//! WebSub (formerly PubSubHubbub) push subscriptions
+//!
+//! https://www.w3.org/TR/websub/
+//!
+//! Feeds advertising a hub are subscribed to after a successful poll, the
+//! hub then pushes new content to the embedded callback endpoint. Polling
+//! slows down to the maximum interval while the lease is active, and goes
+//! back to normal once it lapsed.
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use hyper::body::HttpBody;
+use hyper::header::{HeaderValue, CONTENT_TYPE};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use once_cell::sync::OnceCell;
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+use tokio::sync::Mutex;
+
+use crate::client::{self, FeedError};
+use crate::data::{Database, Feed, FeedId, Hub};
+use crate::feed::Rss;
+
+static CALLBACK_BASE: OnceCell<String> = OnceCell::new();
+
+/// Requested lease, hubs may grant a different one
+const LEASE_SECONDS: u64 = 7 * 24 * 60 * 60;
+/// Renew the subscription this long before the lease expires
+const RENEW_BEFORE: Duration = Duration::from_secs(24 * 60 * 60);
+/// Send the request again if the hub didn't verify it in time
+const PENDING_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+const TOKEN_LEN: usize = 16;
+const SECRET_LEN: usize = 20;
+
+/// `callback_base` is the public URL of the callback endpoint
+pub fn init(callback_base: &str) {
+    CALLBACK_BASE
+        .set(callback_base.trim_end_matches('/').to_owned())
+        .expect("CALLBACK_BASE already initialized");
+}
+
+pub fn enabled() -> bool {
+    CALLBACK_BASE.get().is_some()
+}
+
+fn callback_url(token: &str) -> String {
+    format!(
+        "{}/{}",
+        CALLBACK_BASE.get().expect("CALLBACK_BASE not initialized"),
+        token
+    )
+}
+
+/// Whether pushes are expected, so polling can slow down
+pub fn lease_active(feed: &Feed) -> bool {
+    matches!(
+        feed.hub.as_ref().and_then(|hub| hub.lease_expires),
+        Some(expires) if expires > SystemTime::now()
+    )
+}
+
+fn needs_subscribe(hub: Option<&Hub>, hub_url: &str, topic: &str) -> bool {
+    let hub = match hub {
+        Some(hub) if hub.url == hub_url && hub.topic == topic => hub,
+        _ => return true,
+    };
+    let now = SystemTime::now();
+    match hub.lease_expires {
+        Some(expires) => expires < now + RENEW_BEFORE,
+        None => hub.requested_at + PENDING_TIMEOUT < now,
+    }
+}
+
+/// Whether `hub.secret` can be sent to the hub, it's in plain text over
+/// `http://`, anyone on the way could sign forged pushes with it.
+/// Loopback hubs are fine, the secret never leaves the host
+fn is_secure_hub(hub_url: &str) -> bool {
+    let url = match url::Url::parse(hub_url) {
+        Ok(url) => url,
+        Err(_) => return false,
+    };
+    match (url.scheme(), url.host()) {
+        ("https", _) => true,
+        ("http", Some(url::Host::Domain(domain))) => domain == "localhost",
+        ("http", Some(url::Host::Ipv4(ip))) => ip.is_loopback(),
+        ("http", Some(url::Host::Ipv6(ip))) => ip.is_loopback(),
+        _ => false,
+    }
+}
+
+/// Subscribe the feed to `hub_url`, unless there is a valid or pending subscription.
+/// Insecure hubs are never subscribed to, the feed is polled as usual
+pub async fn subscribe_if_needed(
+    db: Arc<Mutex<Database>>,
+    feed_id: FeedId,
+    hub_url: &str,
+    topic: &str,
+) -> Result<(), FeedError> {
+    if !is_secure_hub(hub_url) {
+        return Ok(());
+    }
+    let hub = {
+        let mut db = db.lock().await;
+        let feed = match db.get_feed(feed_id) {
+            Some(feed) => feed,
+            // user unsubscribed in the meantime
+            None => return Ok(()),
+        };
+        if !needs_subscribe(feed.hub.as_ref(), hub_url, topic) {
+            return Ok(());
+        }
+        let hub = match feed.hub {
+            // Renewal, keep the callback so pushes in flight still arrive
+            Some(hub) if hub.url == hub_url && hub.topic == topic => Hub {
+                requested_at: SystemTime::now(),
+                ..hub
+            },
+            _ => Hub {
+                callback: callback_url(&random_hex(TOKEN_LEN)),
+                secret: random_hex(SECRET_LEN),
+                url: hub_url.to_owned(),
+                topic: topic.to_owned(),
+                lease_expires: None,
+                requested_at: SystemTime::now(),
+            },
+        };
+        db.set_hub(feed_id, Some(hub.clone()));
+        hub
+    };
+
+    let lease_seconds = LEASE_SECONDS.to_string();
+    client::http_client()
+        .post(&hub.url)
+        .form(&[
+            ("hub.mode", "subscribe"),
+            ("hub.topic", &hub.topic),
+            ("hub.callback", &hub.callback),
+            ("hub.secret", &hub.secret),
+            ("hub.lease_seconds", &lease_seconds),
+        ])
+        .timeout(client::read_timeout())
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Best effort, the callback answers pushes with 410 Gone anyway
+pub async fn unsubscribe(hub: Hub) {
+    let result = client::http_client()
+        .post(&hub.url)
+        .form(&[
+            ("hub.mode", "unsubscribe"),
+            ("hub.topic", &hub.topic),
+            ("hub.callback", &hub.callback),
+        ])
+        .timeout(client::read_timeout())
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status());
+    if let Err(e) = result {
+        eprintln!("Warning: failed to unsubscribe from hub {}: {}", hub.url, e);
+    }
+}
+
+/// Serve the callback endpoint, `on_push` is called with feeds pushed by hubs
+pub fn start<F, Fut>(
+    listener: TcpListener,
+    db: Arc<Mutex<Database>>,
+    on_push: F,
+) -> hyper::Result<()>
+where
+    F: Fn(Feed, Rss) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), anyhow::Error>> + Send + 'static,
+{
+    listener
+        .set_nonblocking(true)
+        .expect("failed to set the listener non-blocking");
+    let make_service = make_service_fn(move |_| {
+        let db = db.clone();
+        let on_push = on_push.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                handle(req, db.clone(), on_push.clone())
+            }))
+        }
+    });
+    let server = Server::from_tcp(listener)?.serve(make_service);
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            eprintln!("Error: WebSub callback server: {}", e);
+        }
+    });
+    Ok(())
+}
+
+async fn handle<F, Fut>(
+    req: Request<Body>,
+    db: Arc<Mutex<Database>>,
+    on_push: F,
+) -> Result<Response<Body>, Infallible>
+where
+    F: Fn(Feed, Rss) -> Fut,
+    Fut: Future<Output = Result<(), anyhow::Error>> + Send + 'static,
+{
+    let token = req.uri().path().rsplit('/').next().unwrap_or_default();
+    let callback = callback_url(token);
+    let feed = db.lock().await.find_by_hub_callback(&callback);
+    let resp = match *req.method() {
+        Method::GET => verify_intent(&req, &db, feed).await,
+        Method::POST => match feed {
+            Some(feed) => receive_content(req, feed, on_push).await,
+            None => status(StatusCode::GONE),
+        },
+        _ => status(StatusCode::METHOD_NOT_ALLOWED),
+    };
+    Ok(resp)
+}
+
+/// Verification of subscription intent, see section 5.3 of the spec
+async fn verify_intent(
+    req: &Request<Body>,
+    db: &Mutex<Database>,
+    feed: Option<Feed>,
+) -> Response<Body> {
+    let mut mode = None;
+    let mut topic = None;
+    let mut challenge = None;
+    let mut lease_seconds = None;
+    for (name, value) in
+        url::form_urlencoded::parse(req.uri().query().unwrap_or_default().as_bytes())
+    {
+        match &*name {
+            "hub.mode" => mode = Some(value.into_owned()),
+            "hub.topic" => topic = Some(value.into_owned()),
+            "hub.challenge" => challenge = Some(value.into_owned()),
+            "hub.lease_seconds" => lease_seconds = value.parse::<u64>().ok(),
+            _ => (),
+        }
+    }
+
+    let hub = feed.as_ref().and_then(|feed| feed.hub.as_ref());
+    match (mode.as_deref(), challenge) {
+        (Some("subscribe"), Some(challenge)) => match hub {
+            Some(hub) if topic.as_ref() == Some(&hub.topic) => {
+                let lease = Duration::from_secs(lease_seconds.unwrap_or(LEASE_SECONDS));
+                let hub = Hub {
+                    lease_expires: Some(SystemTime::now() + lease),
+                    ..hub.clone()
+                };
+                db.lock().await.set_hub(feed.unwrap().id(), Some(hub));
+                Response::new(Body::from(challenge))
+            }
+            _ => status(StatusCode::NOT_FOUND),
+        },
+        // Only confirm unsubscriptions we are no longer interested in
+        (Some("unsubscribe"), Some(challenge)) if hub.is_none() => {
+            Response::new(Body::from(challenge))
+        }
+        (Some("denied"), _) => {
+            if let Some(feed) = feed {
+                eprintln!("Warning: hub denied the subscription of {}", feed.link);
+                db.lock().await.set_hub(feed.id(), None);
+            }
+            status(StatusCode::OK)
+        }
+        _ => status(StatusCode::NOT_FOUND),
+    }
+}
+
+/// Content distribution, see section 7 of the spec
+async fn receive_content<F, Fut>(req: Request<Body>, feed: Feed, on_push: F) -> Response<Body>
+where
+    F: Fn(Feed, Rss) -> Fut,
+    Fut: Future<Output = Result<(), anyhow::Error>> + Send + 'static,
+{
+    let hub = feed.hub.as_ref().expect("found by hub callback");
+    let signature = req.headers().get("X-Hub-Signature").cloned();
+    let content_type = req.headers().get(CONTENT_TYPE).cloned();
+    let body = match read_body(req.into_body()).await {
+        Some(body) => body,
+        None => return status(StatusCode::PAYLOAD_TOO_LARGE),
+    };
+    // Still a success, so the hub doesn't retry a forged request
+    if !signature.is_some_and(|signature| verify_signature(&hub.secret, &signature, &body)) {
+        eprintln!("Warning: invalid WebSub signature for {}", feed.link);
+        return status(StatusCode::ACCEPTED);
+    }
+
+    match client::parse_feed_body(&feed.link, content_type.as_ref(), &body) {
+        Ok(rss) => {
+            let link = feed.link.clone();
+            let push = on_push(feed, rss);
+            tokio::spawn(async move {
+                if let Err(e) = push.await {
+                    eprintln!("Error: failed to push updates of {}: {}", link, e);
+                }
+            });
+        }
+        Err(e) => eprintln!("Warning: invalid WebSub content for {}: {}", feed.link, e),
+    }
+    status(StatusCode::ACCEPTED)
+}
+
+/// `None` if the body is larger than the feed size limit
+async fn read_body(mut body: Body) -> Option<Vec<u8>> {
+    let limit = client::max_feed_size();
+    let mut buf = Vec::new();
+    while let Some(chunk) = body.data().await {
+        buf.extend_from_slice(&chunk.ok()?);
+        if limit != 0 && buf.len() as u64 > limit {
+            return None;
+        }
+    }
+    Some(buf)
+}
+
+/// `signature` is `X-Hub-Signature`, e.g. `sha256=<hex digest>`
+fn verify_signature(secret: &str, signature: &HeaderValue, body: &[u8]) -> bool {
+    let (method, digest) = match signature.to_str().ok().and_then(|s| s.split_once('=')) {
+        Some(pair) => pair,
+        None => return false,
+    };
+    let algorithm = match method {
+        "sha1" => hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+        "sha256" => hmac::HMAC_SHA256,
+        "sha384" => hmac::HMAC_SHA384,
+        "sha512" => hmac::HMAC_SHA512,
+        _ => return false,
+    };
+    let digest = match from_hex(digest) {
+        Some(digest) => digest,
+        None => return false,
+    };
+    let key = hmac::Key::new(algorithm, secret.as_bytes());
+    hmac::verify(&key, body, &digest).is_ok()
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    let mut resp = Response::new(Body::empty());
+    *resp.status_mut() = code;
+    resp
+}
+
+fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0; len];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("failed to generate random bytes");
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use tokio::sync::mpsc;
+
+    use super::*;
+
+    fn sign(secret: &str, body: &[u8]) -> String {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+        let tag = hmac::sign(&key, body);
+        let hex: String = tag.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+        format!("sha256={}", hex)
+    }
+
+    #[test]
+    fn signatures() {
+        let body = b"<rss/>";
+        let signature = HeaderValue::from_str(&sign("secret", body)).unwrap();
+        assert!(verify_signature("secret", &signature, body));
+        assert!(!verify_signature("other", &signature, body));
+        assert!(!verify_signature("secret", &signature, b"<rss></rss>"));
+
+        let sha1 = HeaderValue::from_static("sha1=1c3d7ba6bab0f4c8ef6a6c6ab2a5c3e1b96d4c9c");
+        assert!(!verify_signature("secret", &sha1, body));
+        for invalid in ["sha256", "md5=00", "sha256=zz", "sha256=abc"] {
+            let signature = HeaderValue::from_static(invalid);
+            assert!(!verify_signature("secret", &signature, body), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn secure_hubs() {
+        for url in [
+            "https://example.com/hub",
+            "http://localhost:8080/hub",
+            "http://127.0.0.1/hub",
+            "http://[::1]/hub",
+        ] {
+            assert!(is_secure_hub(url), "{}", url);
+        }
+        for url in ["http://example.com/hub", "http://10.0.0.1/hub", "hub"] {
+            assert!(!is_secure_hub(url), "{}", url);
+        }
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!(from_hex("00ff7F"), Some(vec![0, 255, 127]));
+        assert_eq!(from_hex("0"), None);
+        assert_eq!(from_hex("g0"), None);
+        assert_eq!(random_hex(TOKEN_LEN).len(), TOKEN_LEN * 2);
+    }
+
+    const FEED: &str = r#"<?xml version="1.0"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <title>pushed</title>
+  <link rel="self" href="http://example.com/topic"/>
+  <link rel="hub" href="http://example.com/hub"/>
+  <entry><id>1</id><title>entry</title><link href="/entry"/></entry>
+</feed>"#;
+
+    /// A stand-in hub accepts the subscription request, the test itself
+    /// plays the hub for verification and content distribution
+    #[tokio::test]
+    async fn subscription() {
+        client::init_test_client();
+
+        let hub_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        hub_listener.set_nonblocking(true).unwrap();
+        let hub_url = format!("http://{}/", hub_listener.local_addr().unwrap());
+        let (form_tx, mut form_rx) = mpsc::unbounded_channel();
+        let hub = Server::from_tcp(hub_listener)
+            .unwrap()
+            .serve(make_service_fn(move |_| {
+                let form_tx = form_tx.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                        let form_tx = form_tx.clone();
+                        async move {
+                            let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                            let form: HashMap<String, String> =
+                                url::form_urlencoded::parse(&body).into_owned().collect();
+                            form_tx.send(form).unwrap();
+                            Ok::<_, Infallible>(status(StatusCode::ACCEPTED))
+                        }
+                    }))
+                }
+            }));
+        tokio::spawn(hub);
+
+        let callback_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let callback_base = format!("http://{}/websub", callback_listener.local_addr().unwrap());
+        init(&callback_base);
+
+        let path = std::env::temp_dir().join(format!("rssbot-websub-{}.json", std::process::id()));
+        let mut db = Database::create(path.clone()).unwrap();
+        let link = "http://example.com/feed";
+        db.subscribe(1, link, &Rss::default(), &Default::default());
+        let feed_id = crate::data::feed_id(link, &Default::default());
+        let db = Arc::new(Mutex::new(db));
+
+        let (push_tx, mut push_rx) = mpsc::unbounded_channel();
+        start(
+            callback_listener,
+            db.clone(),
+            move |feed: Feed, rss: Rss| {
+                let push_tx = push_tx.clone();
+                async move {
+                    push_tx.send((feed.link, rss)).unwrap();
+                    Ok(())
+                }
+            },
+        )
+        .unwrap();
+
+        let topic = "http://example.com/topic";
+        // The secret would be sent in plain text
+        subscribe_if_needed(db.clone(), feed_id, "http://example.com/hub", topic)
+            .await
+            .unwrap();
+        assert!(db.lock().await.get_feed(feed_id).unwrap().hub.is_none());
+
+        subscribe_if_needed(db.clone(), feed_id, &hub_url, topic)
+            .await
+            .unwrap();
+        let form = form_rx.recv().await.unwrap();
+        assert_eq!(form["hub.mode"], "subscribe");
+        assert_eq!(form["hub.topic"], topic);
+        let callback = form["hub.callback"].clone();
+        let secret = form["hub.secret"].clone();
+        assert!(callback.starts_with(&callback_base));
+
+        // Pending, no duplicated request
+        subscribe_if_needed(db.clone(), feed_id, &hub_url, topic)
+            .await
+            .unwrap();
+        assert!(form_rx.try_recv().is_err());
+
+        let http = client::http_client();
+        let verify = |topic: &str| {
+            http.get(&callback)
+                .query(&[
+                    ("hub.mode", "subscribe"),
+                    ("hub.topic", topic),
+                    ("hub.challenge", "challenge"),
+                    ("hub.lease_seconds", "3600"),
+                ])
+                .send()
+        };
+        let resp = verify("http://example.com/other").await.unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+        assert!(!lease_active(&db.lock().await.all_feeds()[0]));
+        let resp = verify(topic).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.text().await.unwrap(), "challenge");
+        assert!(lease_active(&db.lock().await.all_feeds()[0]));
+
+        let push = |signature: String| {
+            http.post(&callback)
+                .header(CONTENT_TYPE, "application/atom+xml")
+                .header("X-Hub-Signature", signature)
+                .body(FEED)
+                .send()
+        };
+        // Ignored but acknowledged
+        let resp = push(sign("forged", FEED.as_bytes())).await.unwrap();
+        assert!(resp.status().is_success());
+        let resp = push(sign(&secret, FEED.as_bytes())).await.unwrap();
+        assert!(resp.status().is_success());
+        let (pushed_link, rss) = push_rx.recv().await.unwrap();
+        assert_eq!(pushed_link, link);
+        assert_eq!(rss.title, "pushed");
+        assert_eq!(rss.hub.as_deref(), Some("http://example.com/hub"));
+        assert_eq!(
+            rss.items[0].link.as_deref(),
+            Some("http://example.com/entry")
+        );
+        assert!(push_rx.try_recv().is_err());
+
+        let resp = http
+            .post(callback_url("unknown"))
+            .body(FEED)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::GONE);
+
+        let _ = std::fs::remove_file(path);
+    }
+}